@@ -0,0 +1,555 @@
+use crate::{
+    frontend::{
+        self, depfile::Dependencies, features::CompilerProfile, grammar, grammar::Include, Context,
+        FileId, FileSourceProvider, Parser, SourceDir, SourceProvider,
+    },
+    layout::{self, Layout},
+    translator::{
+        self, bitfields,
+        filter::{ItemFilter, ItemPatterns},
+        symbols, Arch, CTypes, DataModel,
+    },
+};
+use indexmap::IndexMap;
+use lang_c::env::Env;
+use similar::TextDiff;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("no root header specified")]
+    NoRoot,
+    #[error("invalid define: {0:?}")]
+    InvalidDefine(String),
+    #[error("invalid item pattern: {0}")]
+    InvalidPattern(#[from] regex::Error),
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    Frontend(#[from] frontend::Error),
+}
+
+/// Configures and runs cpr, much like `bindgen::Builder`:
+///
+/// ```ignore
+/// let bindings = cpr::Builder::new()
+///     .header("include/foo.h")
+///     .include_dir("foo", "include")
+///     .define("FOO_STATIC")
+///     .generate()?;
+/// bindings.write_to("src/generated")?;
+/// ```
+#[derive(Default)]
+pub struct Builder {
+    roots: Vec<Root>,
+    include_dirs: Vec<SourceDir>,
+    defines: Vec<String>,
+    arch: Arch,
+    profile: CompilerProfile,
+    data_model: Option<DataModel>,
+    ctypes: CTypes,
+    type_overrides: HashMap<String, String>,
+    provider: Option<Box<dyn SourceProvider>>,
+    crate_name: Option<String>,
+    layout: Layout,
+    header_features: bool,
+    allow: ItemPatterns,
+    block: ItemPatterns,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a header to start parsing from. Its parent directory is
+    /// searched for quoted includes, and names its package (and the
+    /// generated crate, for the first root). `<name.h>` is looked up in the
+    /// include directories instead, as with `#include <name.h>`.
+    ///
+    /// Roots are parsed in the order added, by the same parser, so names
+    /// defined by a root are visible to the following ones.
+    pub fn header<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.roots.push(Root {
+            path: path.into(),
+            pkg: None,
+        });
+        self
+    }
+
+    /// Adds a header to start parsing from, like `header`, but classifies
+    /// it and the headers next to it as `pkg` instead of the name of its
+    /// parent directory. `<name.h>` roots are classified by the include
+    /// directory they're found in.
+    pub fn header_with_pkg<P: Into<PathBuf>, S: Into<String>>(mut self, path: P, pkg: S) -> Self {
+        self.roots.push(Root {
+            path: path.into(),
+            pkg: Some(pkg.into()),
+        });
+        self
+    }
+
+    /// Adds a system include directory, searched in the order added.
+    /// `pkg` classifies the headers found there, ie. `um`.
+    pub fn include_dir<S: Into<String>, P: Into<PathBuf>>(mut self, pkg: S, path: P) -> Self {
+        self.include_dirs.push(SourceDir {
+            pkg: pkg.into(),
+            path: path.into(),
+        });
+        self
+    }
+
+    /// Adds the include directories of a Windows 10 Kit and an MSVC
+    /// install, see `devenv` for autodetecting them.
+    pub fn windows_sdk<P: AsRef<Path>>(self, kits_path: P, msvc_path: P) -> Self {
+        let kits = kits_path.as_ref();
+        self.include_dir("ucrt", kits.join("ucrt"))
+            .include_dir("shared", kits.join("shared"))
+            .include_dir("um", kits.join("um"))
+            .include_dir("km", kits.join("km"))
+            .include_dir("vc", msvc_path.as_ref())
+    }
+
+    /// Predefines a name, as with `-D`: either `NAME` or `NAME=VALUE`
+    pub fn define<S: Into<String>>(mut self, define: S) -> Self {
+        self.defines.push(define.into());
+        self
+    }
+
+    pub fn arch(mut self, arch: Arch) -> Self {
+        self.arch = arch;
+        self
+    }
+
+    /// Answers to `__has_attribute` and the other feature-test operators,
+    /// defaults to `CompilerProfile::msvc()`
+    pub fn compiler_profile(mut self, profile: CompilerProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Sizes of the C types, defaults to the data model of Windows on the
+    /// architecture, ie. `DataModel::LLP64` for x86-64.
+    pub fn data_model(mut self, data_model: DataModel) -> Self {
+        self.data_model = Some(data_model);
+        self
+    }
+
+    /// How to spell the C types, defaults to fixed-width types like `i32`
+    pub fn ctypes(mut self, ctypes: CTypes) -> Self {
+        self.ctypes = ctypes;
+        self
+    }
+
+    /// Uses `rust_type` instead of translating the C type `name`, ie.
+    /// `.type_override("GUID", "::winapi::shared::guiddef::GUID")`. Struct,
+    /// union and enum tags are named with their prefix, ie. `struct_FOO`.
+    pub fn type_override<S: Into<String>, T: Into<String>>(
+        mut self,
+        name: S,
+        rust_type: T,
+    ) -> Self {
+        self.type_overrides.insert(name.into(), rust_type.into());
+        self
+    }
+
+    /// Reads sources from `provider` instead of the file system. Include
+    /// directories are ignored, since they're only used by the default
+    /// `FileSourceProvider`.
+    pub fn source_provider(mut self, provider: Box<dyn SourceProvider>) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    /// Name of the generated crate, defaults to the package of the first
+    /// root header. Unused with `Layout::Workspace`, which names crates
+    /// after their package.
+    pub fn crate_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.crate_name = Some(name.into());
+        self
+    }
+
+    /// How to arrange generated items into modules, defaults to a module
+    /// tree mirroring the include directories.
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Gates each header's module behind a Cargo feature, like the winapi
+    /// crate does, so dependants only compile the headers they need. Each
+    /// feature enables the features of the headers it refers to. Has no
+    /// effect on `Layout::Flat`.
+    pub fn header_features(mut self, enabled: bool) -> Self {
+        self.header_features = enabled;
+        self
+    }
+
+    /// Only emits functions whose name matches `pattern`, and the types they
+    /// need. See `ItemPatterns` for how patterns are matched.
+    pub fn allow_function<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.allow.functions.push(pattern.into());
+        self
+    }
+
+    /// Only emits types whose name matches `pattern`, and the types they need
+    pub fn allow_type<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.allow.types.push(pattern.into());
+        self
+    }
+
+    /// Only emits constants whose name matches `pattern`
+    pub fn allow_constant<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.allow.constants.push(pattern.into());
+        self
+    }
+
+    /// Only emits items declared in headers matching `pattern`, and the
+    /// types they need
+    pub fn allow_header<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.allow.headers.push(pattern.into());
+        self
+    }
+
+    /// Never emits functions whose name matches `pattern`
+    pub fn block_function<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.block.functions.push(pattern.into());
+        self
+    }
+
+    /// Never emits types whose name matches `pattern`, even when needed by
+    /// other items
+    pub fn block_type<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.block.types.push(pattern.into());
+        self
+    }
+
+    /// Never emits constants whose name matches `pattern`
+    pub fn block_constant<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.block.constants.push(pattern.into());
+        self
+    }
+
+    /// Never emits items declared in headers matching `pattern`
+    pub fn block_header<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.block.headers.push(pattern.into());
+        self
+    }
+
+    /// Parses the root headers and all their dependencies, without translating.
+    pub fn parse(mut self) -> Result<Parser, Error> {
+        self.parse_roots().map(|(parser, _)| parser)
+    }
+
+    /// Parses and translates the root headers and all their dependencies,
+    /// into a single crate.
+    pub fn generate(mut self) -> Result<Bindings, Error> {
+        let (parser, root_ids) = self.parse_roots()?;
+        let config = translator::Config {
+            arch: self.arch,
+            data_model: self.data_model.unwrap_or_else(|| self.arch.data_model()),
+            ctypes: self.ctypes,
+            type_overrides: self.type_overrides.clone(),
+        };
+        let crate_name = match self.crate_name.take() {
+            Some(name) => name,
+            None => parser
+                .provider
+                .info(root_ids[0])
+                .unwrap()
+                .path
+                .dir
+                .pkg
+                .clone(),
+        };
+
+        let filter = ItemFilter::new(&self.allow, &self.block)?;
+        let mut trans_units: Vec<_> = parser
+            .ordered_files
+            .iter()
+            .map(|id| {
+                let unit = parser.units.get(id).unwrap();
+                translator::translate_unit(&config, parser.provider.as_ref(), unit)
+            })
+            .collect();
+        let diagnostics: Vec<_> = symbols::deduplicate(&mut trans_units)
+            .into_iter()
+            .map(|c| {
+                let path = |id| parser.provider.info(id).unwrap().path.to_string();
+                let msg = format!(
+                    "{:?} is declared differently in {} and {}, keeping the former",
+                    c.name,
+                    path(c.kept),
+                    path(c.dropped)
+                );
+                log::warn!("{}", msg);
+                msg
+            })
+            .collect();
+        bitfields::allocate(&mut trans_units, config.data_model);
+        symbols::decay_array_params(&mut trans_units);
+        symbols::collapse_function_pointers(&mut trans_units);
+        filter.apply(parser.provider.as_ref(), &mut trans_units);
+
+        for trans_unit in &trans_units {
+            let unit = parser.units.get(&trans_unit.id).unwrap();
+            let file_info = parser.provider.info(unit.id).unwrap();
+            log::info!(
+                "{} | ({} C => {} Rust)",
+                file_info.path,
+                unit.declarations.len(),
+                trans_unit.toplevels.len(),
+            );
+        }
+        let files = layout::emit(
+            self.layout,
+            self.header_features,
+            &crate_name,
+            &parser,
+            &trans_units,
+        );
+
+        Ok(Bindings {
+            files,
+            dependencies: Dependencies::new(&parser),
+            diagnostics,
+        })
+    }
+
+    fn parse_roots(&mut self) -> Result<(Parser, Vec<FileId>), Error> {
+        if self.roots.is_empty() {
+            return Err(Error::NoRoot);
+        }
+
+        let mut ctx = Context::new();
+        ctx.profile = self.profile.clone();
+        let arch_defines: &[&str] = match self.arch {
+            Arch::X86 => &["_X86", "_M_X86", "_WIN32"],
+            Arch::X86_64 => &["_AMD64_", "_M_AMD64", "_WIN32", "_WIN64"],
+        };
+        for &s in arch_defines {
+            ctx.simple_define(s);
+        }
+        for s in &self.defines {
+            ctx.push(parse_define(s)?);
+        }
+
+        let provider = match self.provider.take() {
+            Some(provider) => provider,
+            None => {
+                log::info!("System dirs:");
+                for sd in &self.include_dirs {
+                    log::info!("{} | {}", sd.pkg, sd.path.display());
+                }
+                Box::new(FileSourceProvider::new(self.include_dirs.clone()))
+            }
+        };
+
+        let mut parser = Parser::new(provider, ctx, Env::with_msvc());
+        let mut root_ids = Vec::new();
+        for root in &self.roots {
+            let (root_dir, root_include) = root.include()?;
+            let root_id = parser
+                .provider
+                .resolve(&mut parser.idgen, &root_dir, &root_include)?;
+            parser.parse_file(root_id)?;
+            root_ids.push(root_id);
+        }
+        log::info!("Done parsing!");
+
+        Ok((parser, root_ids))
+    }
+}
+
+/// A header to start parsing from
+#[derive(Debug, Clone)]
+struct Root {
+    path: PathBuf,
+    pkg: Option<String>,
+}
+
+impl Root {
+    /// Returns the directory to resolve the root from, and how to include it
+    fn include(&self) -> Result<(SourceDir, Include), Error> {
+        if let Some(name) = system_header(&self.path) {
+            let dir = SourceDir {
+                pkg: self.pkg.clone().unwrap_or_else(|| "root".into()),
+                path: PathBuf::from("."),
+            };
+            return Ok((dir, Include::System(name)));
+        }
+
+        let parent = self.path.parent().unwrap_or_else(|| Path::new(""));
+        let dir = SourceDir {
+            pkg: match &self.pkg {
+                Some(pkg) => pkg.clone(),
+                None => parent
+                    .file_name()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("root")
+                    .to_string(),
+            },
+            path: parent.to_path_buf(),
+        };
+        let name = self.path.file_name().ok_or(Error::NoRoot)?;
+        Ok((dir, Include::Quoted(PathBuf::from(name))))
+    }
+}
+
+/// Returns `name.h` for a root given as `<name.h>`, which is looked up in
+/// the include directories.
+pub(crate) fn system_header(path: &Path) -> Option<PathBuf> {
+    let s = path.to_str()?;
+    if s.starts_with('<') && s.ends_with('>') && s.len() > 2 {
+        Some(PathBuf::from(&s[1..s.len() - 1]))
+    } else {
+        None
+    }
+}
+
+/// Parses `NAME` or `NAME=VALUE`, as given to `-D`
+fn parse_define(s: &str) -> Result<grammar::Define, Error> {
+    let invalid = || Error::InvalidDefine(s.to_string());
+    let (name, value) = match s.find('=') {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, "1"),
+    };
+    if name.is_empty() {
+        return Err(invalid());
+    }
+    let value = grammar::token_stream(value).map_err(|_| invalid())?;
+    Ok(grammar::Define::ObjectLike {
+        name: name.to_string(),
+        value,
+    })
+}
+
+/// Output of `Builder::generate`: the files of a crate, in memory
+#[derive(Debug)]
+pub struct Bindings {
+    /// Paths are relative to the crate root, ie. `src/lib.rs`
+    files: IndexMap<PathBuf, String>,
+    dependencies: Dependencies,
+    diagnostics: Vec<String>,
+}
+
+impl Bindings {
+    /// Generated files, as paths relative to the crate root, and contents
+    pub fn files(&self) -> impl Iterator<Item = (&Path, &str)> {
+        self.files.iter().map(|(k, v)| (k.as_path(), v.as_str()))
+    }
+
+    pub fn file<P: AsRef<Path>>(&self, path: P) -> Option<&str> {
+        self.files.get(path.as_ref()).map(|s| s.as_str())
+    }
+
+    /// Every header that was read, ie. for `cargo:rerun-if-changed`
+    pub fn dependencies(&self) -> &Dependencies {
+        &self.dependencies
+    }
+
+    /// Problems found while translating, that didn't prevent generating
+    /// bindings, ie. conflicting declarations
+    pub fn diagnostics(&self) -> &[String] {
+        &self.diagnostics
+    }
+
+    /// Writes all files into `dir`, creating directories as needed
+    pub fn write_to<P: AsRef<Path>>(&self, dir: P) -> io::Result<()> {
+        for (path, contents) in self.files() {
+            let path = dir.as_ref().join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+
+    /// Compares all files with the ones in `dir`, as written by `write_to`.
+    /// Returns a unified diff for each file that differs or is missing, and
+    /// for each `.rs` file in the `src` directory of a generated crate that
+    /// isn't generated anymore, ie. the module of a header that was removed.
+    /// Files outside of `src`, ie. a `build.rs` or tests, are left alone.
+    pub fn check<P: AsRef<Path>>(&self, dir: P) -> io::Result<Vec<FileDiff>> {
+        let mut diffs = Vec::new();
+        for (path, contents) in self.files() {
+            let full_path = dir.as_ref().join(path);
+            let (old, old_name) = match fs::read_to_string(&full_path) {
+                Ok(old) => (old, full_path.display().to_string()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    (String::new(), "/dev/null".to_string())
+                }
+                Err(e) => return Err(e),
+            };
+            if old == contents {
+                continue;
+            }
+
+            let diff = TextDiff::from_lines(old.as_str(), contents)
+                .unified_diff()
+                .header(&old_name, &full_path.display().to_string())
+                .to_string();
+            diffs.push(FileDiff {
+                path: path.to_path_buf(),
+                diff,
+            });
+        }
+
+        let mut extra = Vec::new();
+        let crate_dirs = self
+            .files
+            .keys()
+            .filter(|path| path.file_name() == Some("Cargo.toml".as_ref()))
+            .filter_map(|path| path.parent());
+        for crate_dir in crate_dirs {
+            self.extra_files(dir.as_ref(), &crate_dir.join("src"), &mut extra)?;
+        }
+        for path in extra {
+            let full_path = dir.as_ref().join(&path);
+            let old = fs::read_to_string(&full_path)?;
+            let diff = TextDiff::from_lines(old.as_str(), "")
+                .unified_diff()
+                .header(&full_path.display().to_string(), "/dev/null")
+                .to_string();
+            diffs.push(FileDiff { path, diff });
+        }
+        Ok(diffs)
+    }
+
+    /// Adds the `.rs` files below `dir.join(rel)` that aren't generated to
+    /// `extra`, relative to `dir`
+    fn extra_files(&self, dir: &Path, rel: &Path, extra: &mut Vec<PathBuf>) -> io::Result<()> {
+        let entries = match fs::read_dir(dir.join(rel)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let mut entries = entries.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let name = entry.file_name();
+            let path = rel.join(&name);
+            if entry.file_type()?.is_dir() {
+                self.extra_files(dir, &path, extra)?;
+            } else if path.extension() == Some("rs".as_ref()) && !self.files.contains_key(&path) {
+                extra.push(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A generated file that differs from the one on disk, see `Bindings::check`
+#[derive(Debug)]
+pub struct FileDiff {
+    /// Relative to the crate root, ie. `src/lib.rs`
+    pub path: PathBuf,
+    /// Unified diff from the file on disk to the generated one
+    pub diff: String,
+}
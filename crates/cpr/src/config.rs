@@ -0,0 +1,365 @@
+//! Project configuration, read from `cpr.toml` or from the
+//! `[package.metadata.cpr]` table of a `Cargo.toml`:
+//!
+//! ```toml
+//! [[target]]
+//! name = "projectedfslib"
+//! headers = ["<minwindef.h>", "<projectedfslib.h>"]
+//! output = "out/projectedfslib"
+//! arches = ["x86", "x86-64"]
+//! defines = ["WIN32_LEAN_AND_MEAN"]
+//!
+//! [target.allow]
+//! functions = ["Prj.*"]
+//!
+//! [target.types]
+//! GUID = "::winapi::shared::guiddef::GUID"
+//! ```
+//!
+//! Relative paths are relative to the directory of the configuration file.
+//! When a target has no `include-dirs`, it uses the Windows SDK, either
+//! from `kits-path` and `msvc-path` or autodetected.
+
+use crate::{
+    builder::system_header,
+    devenv,
+    frontend::features::CompilerProfile,
+    layout::Layout,
+    translator::{filter::ItemPatterns, Arch, CTypes, DataModel},
+    Builder,
+};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use thiserror::Error;
+
+pub const CONFIG_FILE_NAME: &str = "cpr.toml";
+pub const MANIFEST_FILE_NAME: &str = "Cargo.toml";
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid configuration: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("{0:?} has no [package.metadata.cpr] table")]
+    NoMetadata(PathBuf),
+    #[error("target {0:?} has no root header")]
+    NoHeader(String),
+    #[error("Windows 10 Kit include path should be autodetected or specified with kits-path")]
+    KitsNotFound,
+    #[error("MSVC include path should be autodetected or specified with msvc-path")]
+    MsvcNotFound,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Project {
+    #[serde(default, rename = "target")]
+    pub targets: Vec<Target>,
+}
+
+/// One set of bindings to generate
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Target {
+    pub name: String,
+    /// Headers to start parsing from, into a single crate
+    #[serde(default)]
+    pub headers: Vec<Header>,
+    /// System include directories, searched in order. Uses the
+    /// Windows SDK when empty.
+    #[serde(default)]
+    pub include_dirs: Vec<IncludeDir>,
+    pub kits_path: Option<PathBuf>,
+    pub msvc_path: Option<PathBuf>,
+    /// Predefined names, as with `-D`: either `NAME` or `NAME=VALUE`
+    #[serde(default)]
+    pub defines: Vec<String>,
+    /// Architectures to generate bindings for, defaults to `x86-64`.
+    /// With more than one, each gets a subdirectory of `output`.
+    #[serde(default)]
+    pub arches: Vec<Arch>,
+    /// Sizes of the C types, ie. `"lp64"`. Defaults to the data model of
+    /// Windows on each architecture.
+    pub data_model: Option<DataModel>,
+    /// How to spell the C types: `"fixed"` (the default), `"std-os-raw"`
+    /// or `"core-ffi"`
+    #[serde(default)]
+    pub ctypes: CTypes,
+    /// Path of the crate to generate
+    pub output: Option<PathBuf>,
+    pub crate_name: Option<String>,
+    /// How to arrange generated items into modules
+    #[serde(default)]
+    pub layout: Layout,
+    /// Whether to gate each header's module behind a Cargo feature
+    #[serde(default)]
+    pub header_features: bool,
+    /// Answers to feature-test operators, ie.
+    /// `[target.compiler-profile]` with `__has_attribute = { packed = 1 }`.
+    /// Defaults to MSVC's, where they're all 0.
+    #[serde(default)]
+    pub compiler_profile: CompilerProfile,
+    /// Items to emit, along with the types they need. Emits everything
+    /// when empty.
+    #[serde(default)]
+    pub allow: ItemPatterns,
+    /// Items never to emit
+    #[serde(default)]
+    pub block: ItemPatterns,
+    /// Rust types to use instead of translating C types, by name. Struct,
+    /// union and enum tags are named with their prefix, ie. `struct_FOO`.
+    #[serde(default)]
+    pub types: HashMap<String, String>,
+}
+
+/// A root header: either a path, ie. `"include/foo.h"`, or a path and the
+/// package to classify it as, ie. `{ path = "include/foo.h", pkg = "foo" }`.
+/// Paths like `"<foo.h>"` are looked up in the include directories.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Header {
+    Path(PathBuf),
+    Package { path: PathBuf, pkg: String },
+}
+
+impl Header {
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Path(path) | Self::Package { path, .. } => path,
+        }
+    }
+
+    pub fn pkg(&self) -> Option<&str> {
+        match self {
+            Self::Path(_) => None,
+            Self::Package { pkg, .. } => Some(pkg),
+        }
+    }
+
+    /// Name of the header without extension, ie. `foo` for `include/foo.h`
+    /// or `<foo.h>`
+    pub fn stem(&self) -> Option<String> {
+        let path = self.path();
+        let path = system_header(path).unwrap_or_else(|| path.to_path_buf());
+        Some(path.file_stem()?.to_string_lossy().into())
+    }
+
+    fn path_mut(&mut self) -> &mut PathBuf {
+        match self {
+            Self::Path(path) | Self::Package { path, .. } => path,
+        }
+    }
+}
+
+impl From<PathBuf> for Header {
+    fn from(path: PathBuf) -> Self {
+        Self::Path(path)
+    }
+}
+
+/// `PATH` or `PKG=PATH`
+impl argh::FromArgValue for Header {
+    fn from_arg_value(value: &str) -> Result<Self, String> {
+        match value.find('=') {
+            Some(0) => Err(format!("missing package name: {:?}", value)),
+            Some(i) => Ok(Self::Package {
+                path: value[i + 1..].into(),
+                pkg: value[..i].into(),
+            }),
+            None => Ok(Self::Path(value.into())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IncludeDir {
+    /// Classifies the headers found there, ie. `um`
+    pub pkg: String,
+    pub path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    package: Option<ManifestPackage>,
+}
+
+#[derive(Deserialize)]
+struct ManifestPackage {
+    metadata: Option<ManifestMetadata>,
+}
+
+#[derive(Deserialize)]
+struct ManifestMetadata {
+    cpr: Option<Project>,
+}
+
+impl Project {
+    /// Looks for `cpr.toml` in `dir`, then for a `Cargo.toml` with a
+    /// `[package.metadata.cpr]` table.
+    pub fn find(dir: &Path) -> Result<Option<Self>, Error> {
+        let path = dir.join(CONFIG_FILE_NAME);
+        if path.is_file() {
+            return Self::from_path(&path).map(Some);
+        }
+
+        let path = dir.join(MANIFEST_FILE_NAME);
+        if path.is_file() {
+            return match Self::from_path(&path) {
+                Ok(project) => Ok(Some(project)),
+                Err(Error::NoMetadata(_)) => Ok(None),
+                Err(e) => Err(e),
+            };
+        }
+
+        Ok(None)
+    }
+
+    /// Reads either a `cpr.toml` or a `Cargo.toml`, depending on the file name
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let source = fs::read_to_string(path)?;
+        let mut project = if path.file_name() == Some(MANIFEST_FILE_NAME.as_ref()) {
+            Self::from_manifest_str(&source)?.ok_or_else(|| Error::NoMetadata(path.into()))?
+        } else {
+            source.parse()?
+        };
+        if let Some(base) = path.parent() {
+            project.resolve_paths(base);
+        }
+        Ok(project)
+    }
+
+    /// Returns the `[package.metadata.cpr]` table of a `Cargo.toml`, if any
+    pub fn from_manifest_str(source: &str) -> Result<Option<Self>, Error> {
+        let manifest: Manifest = toml::from_str(source)?;
+        Ok(manifest
+            .package
+            .and_then(|p| p.metadata)
+            .and_then(|m| m.cpr))
+    }
+
+    /// Makes relative paths relative to `base`
+    pub fn resolve_paths(&mut self, base: &Path) {
+        for target in &mut self.targets {
+            let paths = target
+                .headers
+                .iter_mut()
+                .map(Header::path_mut)
+                .filter(|p| system_header(p).is_none())
+                .chain(target.include_dirs.iter_mut().map(|d| &mut d.path))
+                .chain(target.kits_path.iter_mut())
+                .chain(target.msvc_path.iter_mut())
+                .chain(target.output.iter_mut());
+            for p in paths {
+                if p.is_relative() {
+                    *p = base.join(&p);
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Project {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Self, Error> {
+        Ok(toml::from_str(source)?)
+    }
+}
+
+impl Target {
+    /// Architectures to generate bindings for
+    pub fn arches(&self) -> Vec<Arch> {
+        if self.arches.is_empty() {
+            vec![Arch::default()]
+        } else {
+            self.arches.clone()
+        }
+    }
+
+    /// Where to write the crate for `arch`, if an output was given
+    pub fn output_dir(&self, arch: Arch) -> Option<PathBuf> {
+        let output = self.output.as_ref()?;
+        Some(if self.arches.len() > 1 {
+            output.join(arch.name())
+        } else {
+            output.clone()
+        })
+    }
+
+    pub fn builder(&self, arch: Arch) -> Result<Builder, Error> {
+        if self.headers.is_empty() {
+            return Err(Error::NoHeader(self.name.clone()));
+        }
+
+        let mut builder = Builder::new()
+            .arch(arch)
+            .ctypes(self.ctypes)
+            .layout(self.layout)
+            .header_features(self.header_features)
+            .compiler_profile(self.compiler_profile.clone());
+        for header in &self.headers {
+            builder = match header.pkg() {
+                Some(pkg) => builder.header_with_pkg(header.path(), pkg),
+                None => builder.header(header.path()),
+            };
+        }
+        if self.include_dirs.is_empty() {
+            let kits_path = self
+                .kits_path
+                .clone()
+                .or_else(devenv::get_kits_path)
+                .ok_or(Error::KitsNotFound)?;
+            let msvc_path = self
+                .msvc_path
+                .clone()
+                .or_else(devenv::get_msvc_path)
+                .ok_or(Error::MsvcNotFound)?;
+            builder = builder.windows_sdk(kits_path, msvc_path);
+        }
+        for dir in &self.include_dirs {
+            builder = builder.include_dir(&dir.pkg, &dir.path);
+        }
+        for define in &self.defines {
+            builder = builder.define(define);
+        }
+        if let Some(data_model) = self.data_model {
+            builder = builder.data_model(data_model);
+        }
+        if let Some(name) = &self.crate_name {
+            builder = builder.crate_name(name);
+        }
+
+        let (allow, block) = (&self.allow, &self.block);
+        builder = allow
+            .functions
+            .iter()
+            .fold(builder, Builder::allow_function);
+        builder = allow.types.iter().fold(builder, Builder::allow_type);
+        builder = allow
+            .constants
+            .iter()
+            .fold(builder, Builder::allow_constant);
+        builder = allow.headers.iter().fold(builder, Builder::allow_header);
+        builder = block
+            .functions
+            .iter()
+            .fold(builder, Builder::block_function);
+        builder = block.types.iter().fold(builder, Builder::block_type);
+        builder = block
+            .constants
+            .iter()
+            .fold(builder, Builder::block_constant);
+        builder = block.headers.iter().fold(builder, Builder::block_header);
+        for (name, rust_type) in &self.types {
+            builder = builder.type_override(name, rust_type);
+        }
+        Ok(builder)
+    }
+}
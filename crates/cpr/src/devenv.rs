@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-pub(crate) fn get_kits_path() -> Option<PathBuf> {
+pub fn get_kits_path() -> Option<PathBuf> {
     #[cfg(windows)]
     {
         use std::io;
@@ -55,7 +55,7 @@ pub(crate) fn get_kits_path() -> Option<PathBuf> {
     }
 }
 
-pub(crate) fn get_msvc_path() -> Option<PathBuf> {
+pub fn get_msvc_path() -> Option<PathBuf> {
     #[cfg(windows)]
     {
         use std::io;
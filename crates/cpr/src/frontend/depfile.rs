@@ -0,0 +1,73 @@
+//! List of files read while parsing, for build systems that need to know
+//! when to run cpr again.
+
+use super::Parser;
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone)]
+pub struct Dependencies {
+    /// Paths on disk of every file resolved while parsing, in the order
+    /// they were first included
+    pub paths: Vec<PathBuf>,
+}
+
+impl Dependencies {
+    pub fn new(parser: &Parser) -> Self {
+        let paths = parser
+            .ordered_files
+            .iter()
+            .filter_map(|&id| parser.provider.info(id))
+            .map(|info| info.path.source_path())
+            .collect();
+        Self { paths }
+    }
+
+    /// Formats as a Makefile rule (like `gcc -MD -MP`), with `target`
+    /// depending on every file, and an empty rule for each file so that
+    /// deleting a header doesn't break the build.
+    pub fn to_makefile(&self, target: &Path) -> String {
+        let mut out = String::new();
+        out.push_str(&escape(target));
+        out.push(':');
+        for path in &self.paths {
+            out.push_str(" \\\n  ");
+            out.push_str(&escape(path));
+        }
+        out.push('\n');
+        for path in &self.paths {
+            out.push('\n');
+            out.push_str(&escape(path));
+            out.push_str(":\n");
+        }
+        out
+    }
+
+    /// Formats as `cargo:rerun-if-changed=` lines, to be printed from a
+    /// build script.
+    pub fn to_cargo(&self) -> String {
+        let mut out = String::new();
+        for path in &self.paths {
+            writeln!(out, "cargo:rerun-if-changed={}", path.display()).unwrap();
+        }
+        out
+    }
+}
+
+/// Escapes a path for use in a Makefile rule
+fn escape(path: &Path) -> String {
+    let mut out = String::new();
+    for c in path.display().to_string().chars() {
+        match c {
+            ' ' | '#' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '$' => out.push_str("$$"),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -4,6 +4,7 @@
 
 use super::{ExpandError, HS, THS};
 use crate::frontend::{
+    features::{self, FeatureTest},
     grammar::{Define, MacroParams, Token, TokenSeq},
     Context, SymbolState,
 };
@@ -71,6 +72,16 @@ pub fn expand<'a>(
             continue 'expand_all;
         }
 
+        // Expand `__has_attribute(x)`, `__has_builtin(x)`, etc. from the
+        // compiler profile.
+        if let Token::Name(name) = &first.0 {
+            if let Some(test) = FeatureTest::from_name(name) {
+                let mut saved = vec![];
+                let outcome = expand_feature_test(is, os, &mut saved, ctx, &first, test)?;
+                apply_outcome!(outcome, saved);
+            }
+        }
+
         // Otherwise, if the token sequence begins with an object-like macro, the
         // result is the expansion of the rest of the token sequence beginning with
         // the sequence returned by subst invoked with the replacement token
@@ -145,15 +156,15 @@ fn expand_defined<'a>(
     let next = skip_ws(&mut is, saved)
         .ok_or_else(|| ExpandError::InvalidDefined("EOF immediately after `defined`".into()))?;
 
-    let def = match &next.0 {
-        Token::Name(name) => ctx.lookup(name),
+    let name = match &next.0 {
+        Token::Name(name) => name.clone(),
         Token::Pun('(') => {
             let next = skip_ws(&mut is, saved).ok_or_else(|| {
                 ExpandError::InvalidDefined("EOF immediately after `defined(`".into())
             })?;
 
             let name = match &next.0 {
-                Token::Name(name) => name,
+                Token::Name(name) => name.clone(),
                 tok => {
                     return Err(ExpandError::InvalidDefined(format!(
                         "unexpected token after `defined(`: expected name, got {:#?}",
@@ -172,7 +183,7 @@ fn expand_defined<'a>(
                     )))
                 }
             }
-            ctx.lookup(name)
+            name
         }
         tok => {
             return Err(ExpandError::InvalidDefined(format!(
@@ -182,15 +193,59 @@ fn expand_defined<'a>(
         }
     };
 
-    let val = match def {
-        SymbolState::Undefined => 0,
+    let val = match ctx.lookup(&name) {
         SymbolState::Defined(_) => 1,
+        // feature-test operators aren't macros, but they count as defined
+        SymbolState::Undefined if features::is_feature_test(&name) => 1,
+        SymbolState::Undefined => 0,
     };
 
     os.push(THS(Token::Int(val), first.1.clone()));
     Ok(BranchOutcome::Advance(is))
 }
 
+// Expand `__has_attribute(x)`, `__has_builtin(x)`, etc.
+fn expand_feature_test<'a>(
+    mut is: Box<dyn Iterator<Item = THS> + 'a>,
+    os: &mut Vec<THS>,
+    saved: &mut Vec<THS>,
+    ctx: &Context,
+    first: &THS,
+    test: FeatureTest,
+) -> Result<BranchOutcome<'a>, ExpandError> {
+    match skip_ws(&mut is, saved) {
+        Some(THS(Token::Pun('('), _)) => {}
+        mut val => {
+            // not an invocation, keep verbatim
+            if let Some(tok) = val.take() {
+                saved.push(tok)
+            }
+            return Ok(BranchOutcome::Rewind(is));
+        }
+    }
+
+    // operands can be `name`, `__name__` or `scope::name`
+    let mut operand = String::new();
+    loop {
+        match is.next() {
+            None => {
+                return Err(ExpandError::InvalidFeatureTest(format!(
+                    "missing `)` after {}",
+                    first.0
+                )))
+            }
+            Some(THS(Token::Pun(')'), _)) => break,
+            Some(THS(Token::WS, _)) => {}
+            Some(THS(tok, _)) => operand.push_str(&tok.to_string()),
+        }
+    }
+
+    let val = ctx.profile.query(test, &operand);
+    log::trace!("{}({}) = {}", first.0, operand, val);
+    os.push(THS(Token::Int(val), first.1.clone()));
+    Ok(BranchOutcome::Advance(is))
+}
+
 /// Expands a single macro invocation, either object-like or function-like
 fn expand_single_macro_invocation<'a>(
     mut is: Box<dyn Iterator<Item = THS> + 'a>,
@@ -91,6 +91,8 @@ pub enum ExpandError {
     MissingOpeningParenDefined(String),
     #[error("missing closing parenthesis after defined({0:?})")]
     MissingClosingParenDefined(String),
+    #[error("invalid feature test: {0}")]
+    InvalidFeatureTest(String),
 }
 
 impl ExpandError {
@@ -107,6 +109,7 @@ impl ExpandError {
             ExpandError::MissingMacroParam(..) => false,
             ExpandError::MissingOpeningParenDefined(..) => false,
             ExpandError::MissingClosingParenDefined(..) => false,
+            ExpandError::InvalidFeatureTest(..) => false,
         }
     }
 }
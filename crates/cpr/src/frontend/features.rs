@@ -0,0 +1,231 @@
+//! Feature-test operators usable in `#if` and `#elif`, like `__has_include`
+//! and `__has_attribute`.
+
+use super::{
+    grammar::{Include, Token, TokenSeq},
+    Error,
+};
+use serde::Deserialize;
+use std::{collections::HashMap, convert::TryFrom};
+
+/// Name of the `__has_include` operator
+pub const HAS_INCLUDE: &str = "__has_include";
+/// Name of the `__has_include_next` operator
+pub const HAS_INCLUDE_NEXT: &str = "__has_include_next";
+
+/// Feature-test operators answered from a `CompilerProfile`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeatureTest {
+    /// `__has_attribute(x)`
+    Attribute,
+    /// `__has_cpp_attribute(x)`
+    CppAttribute,
+    /// `__has_declspec_attribute(x)`
+    DeclspecAttribute,
+    /// `__has_builtin(x)`
+    Builtin,
+    /// `__has_feature(x)`
+    Feature,
+    /// `__has_extension(x)`
+    Extension,
+}
+
+impl FeatureTest {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "__has_attribute" => Self::Attribute,
+            "__has_cpp_attribute" => Self::CppAttribute,
+            "__has_declspec_attribute" => Self::DeclspecAttribute,
+            "__has_builtin" => Self::Builtin,
+            "__has_feature" => Self::Feature,
+            "__has_extension" => Self::Extension,
+            _ => return None,
+        })
+    }
+
+    /// Normalizes operand names the way compilers do, ie. `__packed__`
+    /// is looked up as `packed` for attributes.
+    fn normalize<'a>(&self, name: &'a str) -> &'a str {
+        match self {
+            Self::Attribute | Self::CppAttribute | Self::DeclspecAttribute => {
+                if name.len() > 4 && name.starts_with("__") && name.ends_with("__") {
+                    &name[2..name.len() - 2]
+                } else {
+                    name
+                }
+            }
+            _ => name,
+        }
+    }
+}
+
+/// Returns true if `name` is a feature-test operator, which counts as
+/// defined for `#ifdef` and `defined()` purposes.
+pub fn is_feature_test(name: &str) -> bool {
+    name == HAS_INCLUDE || name == HAS_INCLUDE_NEXT || FeatureTest::from_name(name).is_some()
+}
+
+/// Answers to feature-test operators for the compiler we're pretending to be.
+///
+/// Anything that wasn't explicitly set evaluates to 0. In configuration
+/// files, it's a table of answers per operator, ie.
+/// `__has_attribute = { packed = 1 }`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(try_from = "HashMap<String, HashMap<String, i64>>")]
+pub struct CompilerProfile {
+    answers: HashMap<(FeatureTest, String), i64>,
+}
+
+impl CompilerProfile {
+    /// Profile for MSVC, which has none of the clang-style feature tests,
+    /// so they all evaluate to 0.
+    pub fn msvc() -> Self {
+        Default::default()
+    }
+
+    /// Sets the value `test(name)` evaluates to. Attributes usually evaluate
+    /// to a version number, everything else to 1.
+    pub fn set(&mut self, test: FeatureTest, name: &str, value: i64) {
+        let name = test.normalize(name).to_string();
+        self.answers.insert((test, name), value);
+    }
+
+    pub fn query(&self, test: FeatureTest, name: &str) -> i64 {
+        let name = test.normalize(name);
+        self.answers
+            .get(&(test, name.to_string()))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl TryFrom<HashMap<String, HashMap<String, i64>>> for CompilerProfile {
+    type Error = String;
+
+    fn try_from(tests: HashMap<String, HashMap<String, i64>>) -> Result<Self, Self::Error> {
+        let mut profile = Self::msvc();
+        for (test, answers) in tests {
+            let test = FeatureTest::from_name(&test)
+                .ok_or_else(|| format!("unknown feature test: {:?}", test))?;
+            for (name, value) in answers {
+                profile.set(test, &name, value);
+            }
+        }
+        Ok(profile)
+    }
+}
+
+/// Replaces `__has_include(...)` and `__has_include_next(...)` in the
+/// tokens of an `#if` or `#elif` directive with `1` or `0`, as answered
+/// by `has_include`. Must be done before macro expansion, since header
+/// names aren't valid C tokens.
+pub fn replace_has_include(
+    tokens: &TokenSeq,
+    has_include: &mut dyn FnMut(&Include, bool) -> bool,
+) -> Result<TokenSeq, Error> {
+    let mut out = Vec::with_capacity(tokens.0.len());
+    let mut input = &tokens.0[..];
+
+    while let [tok, rest @ ..] = input {
+        input = rest;
+
+        let next = match tok {
+            _ if follows_defined(&out) => None,
+            Token::Name(name) if name == HAS_INCLUDE => Some(false),
+            Token::Name(name) if name == HAS_INCLUDE_NEXT => Some(true),
+            _ => None,
+        };
+        let next = match next {
+            Some(next) => next,
+            None => {
+                out.push(tok.clone());
+                continue;
+            }
+        };
+
+        // skip to opening parenthesis
+        while let [Token::WS, rest @ ..] = input {
+            input = rest;
+        }
+        match input {
+            [Token::Pun('('), rest @ ..] => input = rest,
+            _ => {
+                return Err(Error::InvalidFeatureTest(format!(
+                    "expected `(` after {}",
+                    tok
+                )))
+            }
+        }
+
+        // collect operand until matching closing parenthesis
+        let mut depth = 1;
+        let mut operand = Vec::new();
+        loop {
+            match input {
+                [] => {
+                    return Err(Error::InvalidFeatureTest(format!(
+                        "missing `)` after {}",
+                        tok
+                    )))
+                }
+                [t, rest @ ..] => {
+                    input = rest;
+                    match t {
+                        Token::Pun('(') => depth += 1,
+                        Token::Pun(')') => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    operand.push(t.clone());
+                }
+            }
+        }
+
+        let include = header_name(&operand).ok_or_else(|| {
+            Error::InvalidFeatureTest(format!(
+                "invalid header name in {}: {}",
+                tok,
+                TokenSeq(operand.clone())
+            ))
+        })?;
+        let found = has_include(&include, next);
+        log::debug!("{}({}) = {}", tok, include, found);
+        out.push(Token::bool(found));
+    }
+
+    Ok(out.into())
+}
+
+/// Returns true if the next token is the operand of `defined`, as in
+/// `defined __has_include` or `defined(__has_include)`
+fn follows_defined(out: &[Token]) -> bool {
+    let mut out = out;
+    while let [rest @ .., Token::WS] = out {
+        out = rest;
+    }
+    if let [rest @ .., Token::Pun('(')] = out {
+        out = rest;
+        while let [rest @ .., Token::WS] = out {
+            out = rest;
+        }
+    }
+    matches!(out, [.., Token::Defined])
+}
+
+/// Parses the operand of `__has_include`, either `"foo.h"` or `<foo.h>`
+fn header_name(operand: &[Token]) -> Option<Include> {
+    let mut operand = operand;
+    while let [Token::WS, rest @ ..] | [rest @ .., Token::WS] = operand {
+        operand = rest;
+    }
+
+    match operand {
+        [Token::Str(s)] => Some(Include::Quoted(s.into())),
+        [Token::Pun('<'), inner @ .., Token::Pun('>')] if !inner.is_empty() => {
+            Some(Include::System(TokenSeq(inner.to_vec()).to_string().into()))
+        }
+        _ => None,
+    }
+}
@@ -43,6 +43,15 @@ impl FileSourceProvider {
             .iter()
             .find_map(|dir| self.resolve_from_dir(dir, candidate))
     }
+
+    fn resolve_include(&self, working_dir: &SourceDir, include: &Include) -> Option<FilePath> {
+        match include {
+            Include::System(path) => self.resolve_system(path),
+            Include::Quoted(path) => self
+                .resolve_from_dir(working_dir, path)
+                .or_else(|| self.resolve_system(path)),
+        }
+    }
 }
 
 impl SourceProvider for FileSourceProvider {
@@ -52,13 +61,9 @@ impl SourceProvider for FileSourceProvider {
         working_dir: &SourceDir,
         include: &Include,
     ) -> Result<FileId, Error> {
-        let path = match include {
-            Include::System(path) => self.resolve_system(path),
-            Include::Quoted(path) => self
-                .resolve_from_dir(working_dir, path)
-                .or_else(|| self.resolve_system(path)),
-        }
-        .ok_or_else(|| Error::NotFound(include.clone()))?;
+        let path = self
+            .resolve_include(working_dir, include)
+            .ok_or_else(|| Error::NotFound(include.clone()))?;
 
         let id = self.path_to_id.get(&path).copied().unwrap_or_else(|| {
             let id = idgen.generate_id();
@@ -81,4 +86,29 @@ impl SourceProvider for FileSourceProvider {
         let info = self.id_to_info.get(&id).ok_or(Error::UnknownFileId)?;
         Ok(std::fs::read_to_string(&info.path.source_path())?)
     }
+
+    fn exists(&self, working_dir: &SourceDir, include: &Include) -> bool {
+        self.resolve_include(working_dir, include).is_some()
+    }
+
+    fn exists_next(&self, current_dir: &SourceDir, include: &Include) -> bool {
+        // search system dirs that come after the one the current file was found
+        // in, or all of them if it wasn't found in a system dir. The current
+        // dir may be below it, ie. for a root header in a subdirectory.
+        let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let current_path = canonical(&current_dir.path);
+        let skip = self
+            .system_dirs
+            .iter()
+            .map(|dir| canonical(&dir.path))
+            .enumerate()
+            .filter(|(_, path)| current_path.starts_with(path))
+            .max_by_key(|(_, path)| path.components().count())
+            .map(|(i, _)| i + 1)
+            .unwrap_or_default();
+        let candidate: &Path = include.as_ref();
+        self.system_dirs[skip..]
+            .iter()
+            .any(|dir| self.resolve_from_dir(dir, candidate).is_some())
+    }
 }
@@ -180,6 +180,15 @@ peg::parser! { pub(crate) grammar rules() for str {
             '"' | '<' | '>' | '?' | ',' | '.' | '/' | '@'
           ]) { s.chars().next().unwrap().into() }
 
+    /// Parses the contents of `#pragma comment(lib, "foo.lib")`
+    pub rule pragma_comment_lib() -> String
+        = _ N("comment") _ "(" _ N("lib") _ "," _ t:tok_string() _ ")" eof() {
+            match t {
+                Token::Str(s) => s,
+                _ => unreachable!(),
+            }
+        }
+
     pub rule expr() -> Expr
         = e:expr0() eof() { e }
 
@@ -236,9 +245,6 @@ peg::parser! { pub(crate) grammar rules() for str {
         // highest precedence
     }
 
-    pub rule pragma() -> TokenSeq
-        = "__pragma" t:token_stream() { t }
-
 
     rule traced<T>(e: rule<T>) -> T =
         &(input:$([_]*) {
@@ -314,7 +320,7 @@ impl fmt::Display for Token {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct TokenSeq(pub Vec<Token>);
 
 impl From<Vec<Token>> for TokenSeq {
@@ -3,6 +3,7 @@ use quine_mc_cluskey as qmc;
 use std::collections::HashMap;
 
 // Maps cpr `Expr` to an `u8` term for Quine Mc-Cluskey simplification.
+#[derive(Default)]
 pub struct Terms {
     map: HashMap<Expr, u8>,
 }
@@ -0,0 +1,131 @@
+//! Include graph of a parsed set of headers, exportable as DOT or JSON.
+
+use super::Parser;
+use serde::Serialize;
+use std::fmt::{self, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Dot,
+    Json,
+}
+
+impl argh::FromArgValue for GraphFormat {
+    fn from_arg_value(value: &str) -> Result<Self, String> {
+        match value {
+            "dot" => Ok(Self::Dot),
+            "json" => Ok(Self::Json),
+            s => Err(format!("unknown graph format: {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct IncludeGraph {
+    /// Every file that was parsed, in the order they were first included
+    pub files: Vec<GraphFile>,
+    /// Every `#include` directive that was followed
+    pub includes: Vec<GraphEdge>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GraphFile {
+    pub id: u64,
+    /// Path relative to its source dir, prefixed with the package name
+    pub path: String,
+    /// Path on disk
+    pub source_path: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GraphEdge {
+    pub from: u64,
+    pub to: u64,
+    /// The include as written, ie. `<windows.h>`
+    pub include: String,
+    pub line: u64,
+    /// Conditions of the enclosing `#if` blocks, outermost first
+    pub conditions: Vec<String>,
+}
+
+impl IncludeGraph {
+    pub fn new(parser: &Parser) -> Self {
+        let mut res = Self {
+            files: Default::default(),
+            includes: Default::default(),
+        };
+
+        for &id in &parser.ordered_files {
+            if let Some(info) = parser.provider.info(id) {
+                res.files.push(GraphFile {
+                    id: id.0,
+                    path: info.path.to_string(),
+                    source_path: info.path.source_path().display().to_string(),
+                });
+            }
+
+            if let Some(unit) = parser.units.get(&id) {
+                for dep in &unit.dependencies {
+                    res.includes.push(GraphEdge {
+                        from: id.0,
+                        to: dep.id.0,
+                        include: dep.include.to_string(),
+                        line: dep.lineno.0,
+                        conditions: dep.conditions.iter().map(|c| c.to_string()).collect(),
+                    });
+                }
+            }
+        }
+
+        res
+    }
+
+    pub fn write(&self, format: GraphFormat) -> Result<String, fmt::Error> {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Json => Ok(self.to_json()),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("include graph should serialize to JSON")
+    }
+
+    pub fn to_dot(&self) -> Result<String, fmt::Error> {
+        let mut out = String::new();
+        writeln!(out, "digraph includes {{")?;
+        writeln!(out, "    node [shape=box];")?;
+        for file in &self.files {
+            writeln!(
+                out,
+                "    f{id} [label={label:?}];",
+                id = file.id,
+                label = file.path
+            )?;
+        }
+        for edge in &self.includes {
+            let label = format!("{}:{}", edge.include, edge.line);
+            if edge.conditions.is_empty() {
+                writeln!(
+                    out,
+                    "    f{from} -> f{to} [label={label:?}];",
+                    from = edge.from,
+                    to = edge.to,
+                    label = label,
+                )?;
+            } else {
+                // conditional includes are dashed, with conditions as tooltip
+                writeln!(
+                    out,
+                    "    f{from} -> f{to} [label={label:?}, style=dashed, tooltip={tooltip:?}];",
+                    from = edge.from,
+                    to = edge.to,
+                    label = label,
+                    tooltip = edge.conditions.join(" && "),
+                )?;
+            }
+        }
+        writeln!(out, "}}")?;
+        Ok(out)
+    }
+}
@@ -1,13 +1,18 @@
 //! Drives the process of preprocessing and parsing C header and its dependencies.
 
+pub mod depfile;
 mod expand;
+pub mod features;
 pub mod grammar;
+pub mod graph;
+mod pragma;
 mod utils;
 
 mod file_source_provider;
 pub use file_source_provider::FileSourceProvider;
 
 use expand::{ExpandError, Expandable};
+use features::CompilerProfile;
 use grammar::{Define, Directive, Expr, Include, IncludeDirective, Token, TokenSeq};
 use thiserror::Error;
 
@@ -58,6 +63,8 @@ impl<'a> fmt::Display for LocationDisplay<'a> {
 #[derive(Debug, Clone)]
 pub struct Context {
     defines: HashMap<String, Define>,
+    /// Answers `__has_attribute` and friends
+    pub profile: CompilerProfile,
 }
 
 #[derive(Debug)]
@@ -66,10 +73,17 @@ pub enum SymbolState<'a> {
     Defined(&'a Define),
 }
 
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Context {
     pub fn new() -> Self {
         Context {
             defines: HashMap::new(),
+            profile: CompilerProfile::msvc(),
         }
     }
 
@@ -125,6 +139,12 @@ pub enum Error {
     Expand(#[from] ExpandError),
     #[error("unknown file ID (internal error)")]
     UnknownFileId,
+    #[error("invalid feature test: {0}")]
+    InvalidFeatureTest(String),
+    #[error("invalid pragma operator: {0}")]
+    InvalidPragma(String),
+    #[error("missing `)` after {0}")]
+    UnterminatedPragma(String),
 }
 
 #[derive(PartialEq, Eq)]
@@ -158,8 +178,24 @@ where
 #[derive(Debug)]
 pub struct Unit {
     pub id: FileId,
-    pub dependencies: Vec<Include>,
+    pub dependencies: Vec<Dependency>,
     pub declarations: Vec<UnitDeclaration>,
+    /// Import libraries, from `#pragma comment(lib, "...")`
+    pub libraries: IndexSet<String>,
+}
+
+/// An `#include` directive that was followed
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    pub include: Include,
+    /// File the include resolved to
+    pub id: FileId,
+    /// Line of the `#include` directive
+    pub lineno: LineNo,
+    /// Conditions of the enclosing `#if`, `#elif` and `#else` blocks, outermost
+    /// first. Those of `#elif` and `#else` include the negations of the
+    /// earlier branches.
+    pub conditions: Vec<TokenSeq>,
 }
 
 #[derive(Debug)]
@@ -349,6 +385,43 @@ pub trait SourceProvider {
     ) -> Result<FileId, Error>;
     fn info(&self, id: FileId) -> Option<&FileInfo>;
     fn read(&self, id: FileId) -> Result<String, Error>;
+
+    /// Returns true if `include` would resolve from `working_dir`, without
+    /// assigning it a `FileId`. Used for `__has_include`. Nothing exists by
+    /// default, as if the compiler didn't find any optional header.
+    fn exists(&self, _working_dir: &SourceDir, _include: &Include) -> bool {
+        false
+    }
+
+    /// Like `exists`, but only considers directories searched after
+    /// `current_dir`. Used for `__has_include_next`.
+    fn exists_next(&self, current_dir: &SourceDir, include: &Include) -> bool {
+        self.exists(current_dir, include)
+    }
+}
+
+/// Condition under which an `#elif` (with `condition`) or `#else` branch
+/// is taken: none of the `earlier` branches were, ie. `!(A) && (B)` for
+/// `#if A` followed by `#elif B`.
+fn branch_condition(earlier: &[(bool, TokenSeq)], condition: Option<&TokenSeq>) -> TokenSeq {
+    let negations = earlier.iter().map(|(_, tokens)| !tokens.clone());
+    let terms = negations.chain(condition.map(|tokens| {
+        let mut term = TokenSeq::new();
+        term.0.push('('.into());
+        term.0.extend(tokens.0.iter().cloned());
+        term.0.push(')'.into());
+        term
+    }));
+
+    let mut out = TokenSeq::new();
+    for (i, term) in terms.enumerate() {
+        if i != 0 {
+            out.0
+                .extend(vec![Token::WS, '&'.into(), '&'.into(), Token::WS]);
+        }
+        out = out + term;
+    }
+    out
 }
 
 impl Parser {
@@ -386,26 +459,40 @@ impl Parser {
             id: file_id,
             dependencies: vec![],
             declarations: vec![],
+            libraries: Default::default(),
         };
 
         let mut stack: Vec<(bool, TokenSeq)> = Vec::new();
-        let mut if_stack: Vec<Vec<bool>> = Vec::new();
+        // whether each branch so far was true, and its raw condition
+        let mut if_stack: Vec<Vec<(bool, TokenSeq)>> = Vec::new();
 
         fn path_taken(stack: &[(bool, TokenSeq)]) -> bool {
             stack.iter().all(|(b, _)| *b)
         }
 
-        fn parse_expr(ctx: &Context, tokens: &TokenSeq) -> Expr {
+        fn parse_expr(
+            ctx: &Context,
+            provider: &dyn SourceProvider,
+            dir: &SourceDir,
+            tokens: &TokenSeq,
+        ) -> Result<Expr, Error> {
+            let tokens = features::replace_has_include(tokens, &mut |include, next| {
+                if next {
+                    provider.exists_next(dir, include)
+                } else {
+                    provider.exists(dir, include)
+                }
+            })?;
             let expr_string = tokens
                 .expand(ctx)
                 .expect("all expressions should expand")
                 .to_string();
-            grammar::expr(&expr_string).unwrap_or_else(|e| {
+            Ok(grammar::expr(&expr_string).unwrap_or_else(|e| {
                 panic!(
                     "could not parse expression:\n\n{}\n\ngot error: {:?}",
                     expr_string, e
                 )
-            })
+            }))
         }
 
         'each_line: loop {
@@ -475,6 +562,12 @@ impl Parser {
                                 &file_info.path.dir,
                                 &dep,
                             )?;
+                            unit.dependencies.push(Dependency {
+                                include: dep,
+                                id: dep_id,
+                                lineno,
+                                conditions: stack.iter().map(|(_, ts)| ts.clone()).collect(),
+                            });
                             self.parse_file(dep_id)?;
                         } else {
                             log::debug!("path not taken, not including");
@@ -518,21 +611,27 @@ impl Parser {
                         }
                     }
                     Directive::If(tokens) => {
-                        let expr = parse_expr(&self.ctx, &tokens);
+                        let expr = parse_expr(
+                            &self.ctx,
+                            self.provider.as_ref(),
+                            &file_info.path.dir,
+                            &tokens,
+                        )?;
                         let truthy = expr.truthy();
-                        if_stack.push(vec![truthy]);
+                        if_stack.push(vec![(truthy, tokens.clone())]);
 
-                        let tup = (expr.truthy(), tokens);
+                        let tup = (truthy, tokens);
                         log::debug!("{}:{} if | {} {}", path, lineno, tup.0, tup.1);
                         stack.push(tup)
                     }
                     Directive::Else => {
                         stack.pop().expect("else without if");
                         let mut v = if_stack.pop().expect("else without if");
-                        let branch_taken = v.iter().copied().all(|x| !x);
-                        v.push(branch_taken);
+                        let branch_taken = v.iter().all(|(x, _)| !x);
+                        let condition = branch_condition(&v, None);
+                        v.push((branch_taken, condition.clone()));
 
-                        let tup = (branch_taken, vec![].into());
+                        let tup = (branch_taken, condition);
                         log::debug!("{}:{} else | {} {}", path, lineno, tup.0, tup.1);
                         if_stack.push(v);
                         stack.push(tup);
@@ -540,12 +639,18 @@ impl Parser {
                     Directive::ElseIf(tokens) => {
                         stack.pop().expect("elseif without if");
                         let mut v = if_stack.pop().expect("elseif without if");
-                        let expr = parse_expr(&self.ctx, &tokens);
+                        let expr = parse_expr(
+                            &self.ctx,
+                            self.provider.as_ref(),
+                            &file_info.path.dir,
+                            &tokens,
+                        )?;
                         let truthy = expr.truthy();
-                        let branch_taken = v.iter().copied().all(|x| !x) && truthy;
-                        v.push(truthy);
+                        let branch_taken = v.iter().all(|(x, _)| !x) && truthy;
+                        let condition = branch_condition(&v, Some(&tokens));
+                        v.push((truthy, tokens));
 
-                        let tup = (branch_taken, tokens);
+                        let tup = (branch_taken, condition);
                         log::debug!("{} elseif | {} {}", loc!(), tup.0, tup.1);
                         if_stack.push(v);
                         stack.push(tup);
@@ -556,7 +661,9 @@ impl Parser {
                         log::debug!("endif");
                     }
                     Directive::Pragma(s) => {
-                        log::debug!("{} ignoring pragma: {}", loc!(), s);
+                        if taken {
+                            self.handle_pragma(&mut unit, &loc, &s);
+                        }
                     }
                     Directive::Error(s) => {
                         if taken {
@@ -577,31 +684,45 @@ impl Parser {
                         grammar::token_stream(line).expect("should tokenize everything");
 
                     let mut expanded = tokens.expand(&self.ctx);
-                    'aggregate: loop {
+                    let (expanded, pragmas) = 'aggregate: loop {
                         match expanded {
-                            Ok(_) => break 'aggregate,
-                            Err(e) if e.needs_more() => {
-                                let (_, next_line) =
-                                    lines.next().expect("ran out of lines while aggregating");
-                                let mut next_tokens = grammar::token_stream(next_line)
-                                    .unwrap_or_else(|e| {
-                                        log::error!("Could not tokenize input line:");
-                                        log::error!("| {:?}", next_line);
-                                        log::error!("  {}^", " ".repeat(e.location.offset));
-                                        panic!("Failed to tokenize");
-                                    });
-                                tokens.0.push(Token::WS);
-                                tokens.0.append(&mut next_tokens.0);
-                                expanded = tokens.expand(&self.ctx);
-                            }
+                            Ok(expanded) => match pragma::extract_pragmas(&expanded) {
+                                Ok(res) => break 'aggregate res,
+                                // `__pragma(` with arguments on the next line
+                                Err(Error::UnterminatedPragma(_)) if lines.len() > 0 => {}
+                                Err(e) => {
+                                    log::warn!(
+                                        "{}: {}, leaving the line as is",
+                                        loc.display(self.provider.as_ref()),
+                                        e
+                                    );
+                                    break 'aggregate (expanded, Vec::new());
+                                }
+                            },
+                            Err(e) if e.needs_more() => {}
                             Err(e) => panic!(
                                 "while expanding non-directive line\n\n{}\n\ngot error: {}",
                                 tokens, e
                             ),
                         }
-                    }
 
-                    block.lines.push((lineno, expanded?));
+                        let (_, next_line) =
+                            lines.next().expect("ran out of lines while aggregating");
+                        let mut next_tokens =
+                            grammar::token_stream(next_line).unwrap_or_else(|e| {
+                                log::error!("Could not tokenize input line:");
+                                log::error!("| {:?}", next_line);
+                                log::error!("  {}^", " ".repeat(e.location.offset));
+                                panic!("Failed to tokenize");
+                            });
+                        tokens.0.push(Token::WS);
+                        tokens.0.append(&mut next_tokens.0);
+                        expanded = tokens.expand(&self.ctx);
+                    };
+                    for s in &pragmas {
+                        self.handle_pragma(&mut unit, &loc, s);
+                    }
+                    block.lines.push((lineno, expanded));
 
                     if block.is_degenerate_macro_invocation() {
                         log::debug!(
@@ -619,17 +740,6 @@ impl Parser {
 
                     if block.is_balanced() {
                         let block_str = block.as_string();
-                        match grammar::pragma(&block_str) {
-                            Ok(p) => {
-                                log::debug!("skipping pragma: __pragma{}", p);
-                                block.clear();
-                                continue 'each_line;
-                            }
-                            Err(_e) => {
-                                // log::debug!("pragma error: {:?}", e);
-                            }
-                        }
-
                         match lang_c::parser::translation_unit(&block_str, &self.env.for_parser()) {
                             Ok(mut node) => {
                                 unit.declarations
@@ -683,6 +793,26 @@ impl Parser {
         Ok(())
     }
 
+    /// Handles the contents of `#pragma` directives, as well as `__pragma()`
+    /// and `_Pragma()` operators found after macro expansion.
+    fn handle_pragma(&self, unit: &mut Unit, loc: &Location, pragma: &str) {
+        if let Some(lib) = pragma::parse_comment_lib(pragma) {
+            log::debug!(
+                "{} links against {:?}",
+                loc.display(self.provider.as_ref()),
+                lib
+            );
+            unit.libraries.insert(lib);
+            return;
+        }
+
+        log::debug!(
+            "{} ignoring pragma: {}",
+            loc.display(self.provider.as_ref()),
+            pragma
+        );
+    }
+
     fn parse_constant(&mut self, def: &Define, value: &TokenSeq) -> Option<UnitDeclaration> {
         let s = value.expand(&self.ctx).ok()?.to_string();
         let node = lang_c::parser::constant_expression(&s, &self.env.for_parser()).ok()?;
@@ -716,3 +846,15 @@ impl Parser {
 
 #[cfg(test)]
 mod test_expr;
+
+#[cfg(test)]
+mod test_features;
+
+#[cfg(test)]
+mod test_file_source_provider;
+
+#[cfg(test)]
+mod test_graph;
+
+#[cfg(test)]
+mod test_depfile;
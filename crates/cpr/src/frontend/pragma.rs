@@ -0,0 +1,190 @@
+//! Pragma operators (`__pragma(...)` and `_Pragma("...")`), which can appear
+//! anywhere in a line after macro expansion, unlike `#pragma` directives.
+
+use super::{
+    grammar::{self, Token, TokenSeq},
+    Error,
+};
+
+/// MSVC pragma operator, takes tokens: `__pragma(pack(push, 1))`
+const MSVC_PRAGMA: &str = "__pragma";
+/// C99 pragma operator, takes a string literal: `_Pragma("pack(push, 1)")`
+const C99_PRAGMA: &str = "_Pragma";
+
+/// Removes pragma operators from an expanded token sequence, returning the
+/// remaining tokens, along with the contents of each pragma (as they would
+/// appear after `#pragma`), in order. Fails with `Error::UnterminatedPragma`
+/// when an operator's arguments may continue on the next line.
+pub fn extract_pragmas(tokens: &TokenSeq) -> Result<(TokenSeq, Vec<String>), Error> {
+    let mut out = Vec::with_capacity(tokens.0.len());
+    let mut pragmas = Vec::new();
+    let mut input = &tokens.0[..];
+
+    while let [tok, rest @ ..] = input {
+        input = rest;
+
+        let name = match tok {
+            Token::Name(name) if name == MSVC_PRAGMA || name == C99_PRAGMA => name,
+            tok => {
+                out.push(tok.clone());
+                continue;
+            }
+        };
+
+        while let [Token::WS, rest @ ..] = input {
+            input = rest;
+        }
+        match input {
+            [Token::Pun('('), rest @ ..] => input = rest,
+            _ => return Err(Error::InvalidPragma(format!("expected `(` after {}", name))),
+        }
+
+        let mut depth = 1;
+        let mut operand = Vec::new();
+        loop {
+            match input {
+                [] => return Err(Error::UnterminatedPragma(name.to_string())),
+                [t, rest @ ..] => {
+                    input = rest;
+                    match t {
+                        Token::Pun('(') => depth += 1,
+                        Token::Pun(')') => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    operand.push(t.clone());
+                }
+            }
+        }
+
+        let pragma = if name == C99_PRAGMA {
+            match trim_ws(&operand) {
+                [Token::Str(s)] => destringize(s),
+                _ => {
+                    return Err(Error::InvalidPragma(format!(
+                        "{} expects a single string literal, got: {}",
+                        name,
+                        TokenSeq(operand)
+                    )))
+                }
+            }
+        } else {
+            TokenSeq(trim_ws(&operand).to_vec()).to_string()
+        };
+        pragmas.push(pragma);
+    }
+
+    Ok((out.into(), pragmas))
+}
+
+/// Parses `comment(lib, "wintrust.lib")`, returning the library name
+/// suitable for `#[link(name = ...)]`, ie. `wintrust`.
+pub fn parse_comment_lib(pragma: &str) -> Option<String> {
+    let name = destringize(&grammar::pragma_comment_lib(pragma).ok()?);
+    let stem = if name.to_lowercase().ends_with(".lib") {
+        &name[..name.len() - 4]
+    } else {
+        &name[..]
+    };
+    Some(stem.to_string())
+}
+
+fn trim_ws(tokens: &[Token]) -> &[Token] {
+    let mut tokens = tokens;
+    while let [Token::WS, rest @ ..] | [rest @ .., Token::WS] = tokens {
+        tokens = rest;
+    }
+    tokens
+}
+
+/// As per C99 6.10.9: "deleting the L prefix, if present, deleting the
+/// leading and trailing double-quotes, replacing each escape sequence \" by a
+/// double-quote, and replacing each escape sequence \\ by a single backslash"
+fn destringize(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c @ '"') | Some(c @ '\\') => out.push(c),
+                Some(c) => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::grammar;
+
+    fn extract(input: &str) -> (String, Vec<String>) {
+        let tokens = grammar::token_stream(input).unwrap();
+        let (tokens, pragmas) = extract_pragmas(&tokens).unwrap();
+        (tokens.to_string(), pragmas)
+    }
+
+    #[test]
+    fn msvc_pragma() {
+        assert_eq!(
+            extract("__pragma(warning(push)) int a;"),
+            (" int a;".into(), vec!["warning(push)".into()])
+        );
+        assert_eq!(
+            extract("typedef __pragma( pack(push, 1) ) struct S S;"),
+            ("typedef  struct S S;".into(), vec!["pack(push, 1)".into()])
+        );
+    }
+
+    #[test]
+    fn c99_pragma() {
+        assert_eq!(
+            extract(r#"int a; _Pragma("pack(push,1)") int b;"#),
+            ("int a;  int b;".into(), vec!["pack(push,1)".into()])
+        );
+        assert_eq!(
+            extract(r#"_Pragma("comment(lib, \"foo.lib\")")"#),
+            ("".into(), vec![r#"comment(lib, "foo.lib")"#.into()])
+        );
+    }
+
+    #[test]
+    fn comment_lib() {
+        assert_eq!(
+            parse_comment_lib(r#"comment(lib, "wintrust.lib")"#),
+            Some("wintrust".into())
+        );
+        assert_eq!(
+            parse_comment_lib(r#"comment( lib , "Kernel32.LIB" )"#),
+            Some("Kernel32".into())
+        );
+        assert_eq!(
+            parse_comment_lib(r#"comment(lib, "ntdll")"#),
+            Some("ntdll".into())
+        );
+        assert_eq!(parse_comment_lib(r#"comment(linker, "/foo")"#), None);
+        assert_eq!(parse_comment_lib("warning(push)"), None);
+    }
+
+    #[test]
+    fn invalid_pragma() {
+        let tokens = grammar::token_stream("_Pragma(pack)").unwrap();
+        assert!(matches!(
+            extract_pragmas(&tokens),
+            Err(Error::InvalidPragma(_))
+        ));
+        let tokens = grammar::token_stream("__pragma(pack").unwrap();
+        assert!(matches!(
+            extract_pragmas(&tokens),
+            Err(Error::UnterminatedPragma(_))
+        ));
+    }
+}
@@ -0,0 +1,56 @@
+use super::{depfile::Dependencies, Context};
+use crate::translator::test_translator::{parse_root, provider};
+use indoc::indoc;
+use lang_c::env::Env;
+use std::path::{Path, PathBuf};
+
+#[test]
+fn depfile() {
+    let parser = parse_root(
+        provider(&[
+            ("root.h", "#include \"a.h\"\n#include \"a b.h\"\n"),
+            ("a.h", "#include \"a b.h\"\n"),
+            ("a b.h", "int b;\n"),
+        ]),
+        Context::new(),
+        Env::with_msvc(),
+    );
+    let deps = Dependencies::new(&parser);
+    assert_eq!(
+        deps.paths,
+        vec![
+            PathBuf::from("./root.h"),
+            PathBuf::from("./a.h"),
+            PathBuf::from("./a b.h")
+        ]
+    );
+    assert_eq!(
+        deps.to_makefile(Path::new("out/src/lib.rs")),
+        indoc!(
+            r#"
+            out/src/lib.rs: \
+              ./root.h \
+              ./a.h \
+              ./a\ b.h
+
+            ./root.h:
+
+            ./a.h:
+
+            ./a\ b.h:
+            "#
+        )
+        .trim_start()
+    );
+    assert_eq!(
+        deps.to_cargo(),
+        indoc!(
+            "
+            cargo:rerun-if-changed=./root.h
+            cargo:rerun-if-changed=./a.h
+            cargo:rerun-if-changed=./a b.h
+            "
+        )
+        .trim_start()
+    );
+}
@@ -0,0 +1,61 @@
+use super::{
+    features::{replace_has_include, CompilerProfile, FeatureTest},
+    grammar::{self, Include, Token},
+    Context, Expandable,
+};
+
+fn eval(ctx: &Context, input: &str) -> i64 {
+    let tokens = grammar::token_stream(input).unwrap();
+    let tokens = replace_has_include(&tokens, &mut |include, next| {
+        !next && include == &Include::System("present.h".into())
+    })
+    .unwrap();
+    let expanded = tokens.expand(ctx).unwrap();
+    grammar::expr(&expanded.to_string())
+        .unwrap()
+        .assume_undefined()
+}
+
+#[test]
+fn has_include() {
+    let ctx = Context::new();
+    assert_eq!(eval(&ctx, "__has_include(<present.h>)"), 1);
+    assert_eq!(eval(&ctx, "__has_include( <present.h> )"), 1);
+    assert_eq!(eval(&ctx, "__has_include(<missing.h>)"), 0);
+    assert_eq!(eval(&ctx, r#"__has_include("present.h")"#), 0);
+    assert_eq!(eval(&ctx, "__has_include_next(<present.h>)"), 0);
+    assert_eq!(
+        eval(&ctx, "defined(__has_include) && defined __has_include_next"),
+        1
+    );
+}
+
+#[test]
+fn has_include_invalid() {
+    let tokens = grammar::token_stream("__has_include(foo.h)").unwrap();
+    assert!(replace_has_include(&tokens, &mut |_, _| true).is_err());
+}
+
+#[test]
+fn profile() {
+    let mut ctx = Context::new();
+    assert_eq!(eval(&ctx, "__has_attribute(packed)"), 0);
+    assert_eq!(eval(&ctx, "defined(__has_attribute)"), 1);
+
+    let mut profile = CompilerProfile::msvc();
+    profile.set(FeatureTest::Attribute, "packed", 1);
+    profile.set(FeatureTest::Builtin, "__builtin_expect", 1);
+    ctx.profile = profile;
+
+    assert_eq!(eval(&ctx, "__has_attribute(packed)"), 1);
+    assert_eq!(eval(&ctx, "__has_attribute(__packed__)"), 1);
+    assert_eq!(eval(&ctx, "__has_builtin(__builtin_expect)"), 1);
+    assert_eq!(eval(&ctx, "__has_feature(packed)"), 0);
+
+    // not an invocation, left alone
+    let tokens = grammar::token_stream("__has_builtin").unwrap();
+    assert_eq!(
+        tokens.expand(&ctx).unwrap().0,
+        vec![Token::name("__has_builtin")]
+    );
+}
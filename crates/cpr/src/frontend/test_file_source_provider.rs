@@ -0,0 +1,38 @@
+use super::{grammar::Include, FileSourceProvider, SourceDir, SourceProvider};
+use std::{fs, path::Path};
+
+fn source_dir(path: &Path) -> SourceDir {
+    SourceDir {
+        pkg: "pkg".into(),
+        path: path.into(),
+    }
+}
+
+#[test]
+fn exists_next() {
+    let root = std::env::temp_dir().join(format!("cpr-include-next-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let (first, second) = (root.join("first"), root.join("second"));
+    fs::create_dir_all(first.join("sub")).unwrap();
+    fs::create_dir_all(&second).unwrap();
+    fs::write(first.join("sub/wrapper.h"), "").unwrap();
+    fs::write(first.join("both.h"), "").unwrap();
+    fs::write(second.join("both.h"), "").unwrap();
+    fs::write(first.join("first.h"), "").unwrap();
+
+    let provider = FileSourceProvider::new(vec![source_dir(&first), source_dir(&second)]);
+    let both = Include::System("both.h".into());
+    let first_only = Include::System("first.h".into());
+
+    for current in &[first.clone(), first.join("sub")] {
+        let current = source_dir(current);
+        assert!(provider.exists_next(&current, &both));
+        assert!(!provider.exists_next(&current, &first_only));
+    }
+    // not in a system dir, so all of them are searched
+    let elsewhere = source_dir(&root);
+    assert!(provider.exists_next(&elsewhere, &first_only));
+    assert!(!provider.exists_next(&source_dir(&second), &both));
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,89 @@
+use super::{
+    graph::{GraphFormat, IncludeGraph},
+    Context,
+};
+use crate::translator::test_translator::{parse_root, provider};
+use indoc::indoc;
+use lang_c::env::Env;
+
+#[test]
+fn include_graph() {
+    let parser = parse_root(
+        provider(&[
+            (
+                "root.h",
+                indoc!(
+                    r#"
+                    #include "a.h"
+                    #ifdef _WIN64
+                    #include "b.h"
+                    #elif FOO
+                    #include "b.h"
+                    #else
+                    #include "c.h"
+                    #endif
+                    #if FOO
+                    #elif 1
+                    #include "d.h"
+                    #endif
+                    "#
+                ),
+            ),
+            ("a.h", "#include \"b.h\"\n"),
+            ("b.h", "int b;\n"),
+            ("c.h", "int c;\n"),
+            ("d.h", "int d;\n"),
+        ]),
+        Context::new(),
+        Env::with_msvc(),
+    );
+    let graph = IncludeGraph::new(&parser);
+
+    let paths: Vec<_> = graph.files.iter().map(|f| f.path.as_str()).collect();
+    assert_eq!(
+        paths,
+        vec![
+            "root/root.h",
+            "root/a.h",
+            "root/b.h",
+            "root/c.h",
+            "root/d.h"
+        ]
+    );
+
+    let id = |path: &str| graph.files.iter().find(|f| f.path == path).unwrap().id;
+    let edges: Vec<_> = graph
+        .includes
+        .iter()
+        .map(|e| (e.from, e.to, e.line, e.conditions.clone()))
+        .collect();
+    assert_eq!(
+        edges,
+        vec![
+            (id("root/root.h"), id("root/a.h"), 1, vec![]),
+            // branches are only taken when the earlier ones aren't
+            (
+                id("root/root.h"),
+                id("root/c.h"),
+                7,
+                vec!["!(defined(_WIN64)) && !(FOO)".to_string()]
+            ),
+            (
+                id("root/root.h"),
+                id("root/d.h"),
+                11,
+                vec!["!(FOO) && (1)".to_string()]
+            ),
+            (id("root/a.h"), id("root/b.h"), 1, vec![]),
+        ]
+    );
+
+    let dot = graph.write(GraphFormat::Dot).unwrap();
+    assert!(dot.starts_with("digraph includes {"));
+    assert!(dot.contains("[label=\"root/a.h\"]"));
+    assert!(dot.contains("style=dashed"));
+
+    let json: serde_json::Value =
+        serde_json::from_str(&graph.write(GraphFormat::Json).unwrap()).unwrap();
+    assert_eq!(json["includes"].as_array().unwrap().len(), 4);
+}
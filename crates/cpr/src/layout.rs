@@ -0,0 +1,528 @@
+//! Arranges translated units into the source files of a crate.
+
+use crate::{
+    frontend::{FileId, FilePath, Parser},
+    translator::{self, rg, symbols::SymbolTable},
+};
+use indexmap::IndexMap;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// One module per header, mirroring the include directories,
+    /// ie. `um/WinTrust.h` becomes `um::wintrust` in `src/um/wintrust.rs`
+    #[default]
+    Tree,
+    /// Everything in `src/lib.rs`
+    Flat,
+    /// One crate per package, in a Cargo workspace, ie. `um/WinTrust.h`
+    /// becomes `wintrust` in `um-sys/src/wintrust.rs`. Crates depend on the
+    /// crates whose headers they include or whose items they use. Packages
+    /// that depend on each other are merged into one crate, with a module
+    /// per package, ie. `um-shared-sys/src/um/wintrust.rs`.
+    Workspace,
+}
+
+impl argh::FromArgValue for Layout {
+    fn from_arg_value(value: &str) -> Result<Self, String> {
+        match value {
+            "tree" => Ok(Self::Tree),
+            "flat" => Ok(Self::Flat),
+            "workspace" => Ok(Self::Workspace),
+            s => Err(format!("unknown layout: {:?}", s)),
+        }
+    }
+}
+
+/// Turns a path component into a valid module name, ie. `WinTrust` => `wintrust`
+pub fn module_name(component: &str) -> String {
+    let mut res: String = component
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '_' => c,
+            'A'..='Z' => c.to_ascii_lowercase(),
+            _ => '_',
+        })
+        .collect();
+    if res.is_empty() || res.starts_with(|c: char| c.is_ascii_digit()) {
+        res.insert(0, '_');
+    }
+    if rg::is_keyword(&res) {
+        res.push('_');
+    }
+    res
+}
+
+/// Module path of a header, ie. `["um", "wintrust"]` for `um/WinTrust.h`
+pub fn module_path(path: &FilePath) -> Vec<String> {
+    let mut components = path.pkg_components();
+    if let Some(last) = components.last_mut() {
+        if let Some(stem) = Path::new(last.as_str()).file_stem() {
+            *last = stem.to_string_lossy().into();
+        }
+    }
+    components.iter().map(|c| module_name(c)).collect()
+}
+
+/// Name of the crate generated for a package in a workspace, ie. `um-sys`
+pub fn package_crate_name(pkg: &str) -> String {
+    let name: String = pkg
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '-' => c,
+            'A'..='Z' => c.to_ascii_lowercase(),
+            _ => '-',
+        })
+        .collect();
+    format!("{}-sys", name)
+}
+
+#[derive(Default)]
+struct Module<'a> {
+    unit: Option<(&'a FilePath, &'a rg::Unit)>,
+    children: IndexMap<String, Module<'a>>,
+}
+
+/// Returns the files of a crate named `crate_name` for `units` (or of a
+/// workspace, see `Layout::Workspace`), as paths relative to its root.
+/// Units without items are skipped.
+///
+/// With `header_features`, each header's module is only compiled with the
+/// Cargo feature of the same name, ie. `um-wintrust`, which enables the
+/// features of the headers it refers to. This has no effect on the flat
+/// layout.
+pub fn emit(
+    layout: Layout,
+    header_features: bool,
+    crate_name: &str,
+    parser: &Parser,
+    units: &[rg::Unit],
+) -> IndexMap<PathBuf, String> {
+    let provider = parser.provider.as_ref();
+    let units: Vec<_> = units
+        .iter()
+        .filter(|unit| !unit.toplevels.is_empty())
+        .map(|unit| (&provider.info(unit.id).unwrap().path, unit))
+        .collect();
+
+    let mut files = IndexMap::new();
+    match layout {
+        Layout::Flat => {
+            files.insert(
+                PathBuf::from("Cargo.toml"),
+                manifest(crate_name, &[], &Default::default(), true),
+            );
+            let mut f = String::new();
+            writeln!(f, "{}", translator::prelude()).unwrap();
+            for (path, unit) in units {
+                writeln!(f, "// @generated from {:?}", path.source_path()).unwrap();
+                writeln!(f).unwrap();
+                writeln!(f, "{}", unit).unwrap();
+            }
+            files.insert(PathBuf::from("src").join("lib.rs"), f);
+        }
+        Layout::Tree => {
+            let (root, mod_paths) = module_tree(&units, 0);
+            let tree = Tree {
+                symbols: SymbolTable::new(units.iter().map(|&(_, unit)| unit)),
+                mod_paths,
+                crates: HashMap::new(),
+                header_features,
+            };
+            tree.emit_crate(Path::new(""), crate_name, &[], &root, &mut files);
+        }
+        Layout::Workspace => {
+            let mut packages: IndexMap<&str, Vec<_>> = IndexMap::new();
+            for &(path, unit) in &units {
+                packages
+                    .entry(path.dir.pkg.as_str())
+                    .or_default()
+                    .push((path, unit));
+            }
+
+            let symbols = SymbolTable::new(units.iter().map(|&(_, unit)| unit));
+            let groups = merge_cycles(&package_dependencies(parser, &symbols, &packages));
+
+            let mut roots = Vec::new();
+            let mut crate_units = Vec::new();
+            let mut mod_paths = HashMap::new();
+            let mut crates = HashMap::new();
+            for group in &groups {
+                let name = package_crate_name(&group.join("-"));
+                if group.len() > 1 {
+                    log::warn!(
+                        "packages {} depend on each other, generating them as crate {:?}",
+                        group.join(", "),
+                        name
+                    );
+                }
+                let units: Vec<_> = group
+                    .iter()
+                    .flat_map(|pkg| packages[pkg].iter().copied())
+                    .collect();
+                // a merged crate keeps the package in module paths
+                let skip = if group.len() == 1 { 1 } else { 0 };
+                let (root, paths) = module_tree(&units, skip);
+                mod_paths.extend(paths);
+                for (_, unit) in &units {
+                    crates.insert(unit.id, name.clone());
+                }
+                roots.push((name, root));
+                crate_units.push(units);
+            }
+            let tree = Tree {
+                symbols,
+                mod_paths,
+                crates,
+                header_features,
+            };
+
+            let mut workspace = String::new();
+            writeln!(workspace, "[workspace]").unwrap();
+            writeln!(workspace, "members = [").unwrap();
+            for (name, _) in &roots {
+                writeln!(workspace, "    {:?},", name).unwrap();
+            }
+            writeln!(workspace, "]").unwrap();
+            files.insert(PathBuf::from("Cargo.toml"), workspace);
+
+            for ((name, root), units) in roots.iter().zip(&crate_units) {
+                let deps: Vec<_> = tree
+                    .crate_dependencies(parser, name, units)
+                    .into_iter()
+                    .collect();
+                tree.emit_crate(Path::new(name), name, &deps, root, &mut files);
+            }
+        }
+    }
+    files
+}
+
+/// Returns a `Cargo.toml` for crate `name`, with path dependencies on the
+/// sibling crates `deps`. Unless it's part of a generated workspace, the
+/// crate gets an empty `[workspace]` so it can be built from inside another
+/// workspace.
+fn manifest(
+    name: &str,
+    deps: &[&str],
+    features: &BTreeMap<String, BTreeSet<String>>,
+    standalone: bool,
+) -> String {
+    let mut f = String::new();
+    writeln!(f).unwrap();
+    writeln!(f, "[package]").unwrap();
+    writeln!(f, "name = {:?}", name).unwrap();
+    writeln!(f, "version = \"0.1.0\"").unwrap();
+    writeln!(f, "authors = []").unwrap();
+    writeln!(f, "edition = \"2018\"").unwrap();
+    if !deps.is_empty() {
+        writeln!(f).unwrap();
+        writeln!(f, "[dependencies]").unwrap();
+        for dep in deps {
+            writeln!(f, "{} = {{ path = \"../{}\" }}", dep, dep).unwrap();
+        }
+    }
+    if !features.is_empty() {
+        writeln!(f).unwrap();
+        writeln!(f, "[features]").unwrap();
+        for (feature, enables) in features {
+            let enables: Vec<_> = enables.iter().map(|s| format!("{:?}", s)).collect();
+            writeln!(f, "{} = [{}]", feature, enables.join(", ")).unwrap();
+        }
+    }
+    if standalone {
+        writeln!(f).unwrap();
+        writeln!(f, "# workaround for cpr itself being a workspace").unwrap();
+        writeln!(f, "[workspace]").unwrap();
+    }
+    f
+}
+
+/// Arranges `units` into modules, leaving out the first `skip` components
+/// of their module path. Returns the root module, and the module path of
+/// each unit.
+fn module_tree<'a>(
+    units: &[(&'a FilePath, &'a rg::Unit)],
+    skip: usize,
+) -> (Module<'a>, HashMap<FileId, Vec<String>>) {
+    let mut root = Module::default();
+    let mut taken = HashSet::new();
+    let mut mod_paths = HashMap::new();
+    for &(path, unit) in units {
+        let mut mod_path = module_path(path).split_off(skip);
+        // ie. `foo.h` and `Foo.h`, or `foo.h` and `foo.hpp`
+        let last = mod_path.pop().unwrap();
+        let mut candidate = last.clone();
+        let mut n = 1;
+        while taken.contains(&(mod_path.clone(), candidate.clone())) {
+            n += 1;
+            candidate = format!("{}_{}", last, n);
+        }
+        taken.insert((mod_path.clone(), candidate.clone()));
+        mod_path.push(candidate);
+
+        let mut module = &mut root;
+        for comp in &mod_path {
+            module = module.children.entry(comp.clone()).or_default();
+        }
+        module.unit = Some((path, unit));
+        mod_paths.insert(unit.id, mod_path);
+    }
+    (root, mod_paths)
+}
+
+/// Returns the packages each package needs, either because it includes
+/// one of their headers, or because it uses one of their items
+fn package_dependencies<'a>(
+    parser: &Parser,
+    symbols: &SymbolTable,
+    packages: &IndexMap<&'a str, Vec<(&FilePath, &rg::Unit)>>,
+) -> IndexMap<&'a str, BTreeSet<&'a str>> {
+    let pkg_of: HashMap<FileId, &str> = packages
+        .iter()
+        .flat_map(|(&pkg, units)| units.iter().map(move |(_, unit)| (unit.id, pkg)))
+        .collect();
+    packages
+        .iter()
+        .map(|(&pkg, units)| {
+            let deps = units
+                .iter()
+                .flat_map(|(_, unit)| referenced_units(parser, symbols, unit))
+                .filter_map(|id| pkg_of.get(&id).copied())
+                .filter(|&dep| dep != pkg)
+                .collect();
+            (pkg, deps)
+        })
+        .collect()
+}
+
+/// Groups packages that depend on each other, directly or not, as cargo
+/// rejects dependency cycles between crates. Groups are in the order of
+/// their first package.
+fn merge_cycles<'a>(deps: &IndexMap<&'a str, BTreeSet<&'a str>>) -> Vec<Vec<&'a str>> {
+    let reachable = |pkg: &'a str| {
+        let mut seen = HashSet::new();
+        let mut queue = vec![pkg];
+        while let Some(pkg) = queue.pop() {
+            for &dep in deps.get(pkg).into_iter().flatten() {
+                if seen.insert(dep) {
+                    queue.push(dep);
+                }
+            }
+        }
+        seen
+    };
+    let reached: IndexMap<_, _> = deps.keys().map(|&pkg| (pkg, reachable(pkg))).collect();
+
+    let mut groups = Vec::new();
+    let mut grouped = HashSet::new();
+    for (&pkg, pkg_reached) in &reached {
+        if !grouped.insert(pkg) {
+            continue;
+        }
+        let mut group = vec![pkg];
+        for (&other, other_reached) in &reached {
+            if other != pkg && pkg_reached.contains(other) && other_reached.contains(pkg) {
+                grouped.insert(other);
+                group.push(other);
+            }
+        }
+        groups.push(group);
+    }
+    groups
+}
+
+/// Units `unit` includes, or uses items of
+fn referenced_units(parser: &Parser, symbols: &SymbolTable, unit: &rg::Unit) -> Vec<FileId> {
+    let includes = parser.units[&unit.id].dependencies.iter().map(|d| d.id);
+    let imports = symbols.imports(unit).into_iter().map(|(_, id)| id);
+    includes.chain(imports).collect()
+}
+
+struct Tree {
+    symbols: SymbolTable,
+    /// Module path of each unit, relative to the root of its crate
+    mod_paths: HashMap<FileId, Vec<String>>,
+    /// Crate of each unit, when generating a workspace
+    crates: HashMap<FileId, String>,
+    /// Whether to gate each header's module behind a Cargo feature
+    header_features: bool,
+}
+
+impl Tree {
+    /// Writes `Cargo.toml`, `src/lib.rs` and the modules of the crate
+    /// `name` in `dir`. `deps` are sibling crates of a workspace.
+    fn emit_crate(
+        &self,
+        dir: &Path,
+        name: &str,
+        deps: &[&str],
+        root: &Module,
+        files: &mut IndexMap<PathBuf, String>,
+    ) {
+        let mut features = BTreeMap::new();
+        if self.header_features {
+            self.collect_features(name, root, None, &mut features);
+        }
+        let standalone = self.crates.is_empty();
+        files.insert(
+            dir.join("Cargo.toml"),
+            manifest(name, deps, &features, standalone),
+        );
+
+        let src = dir.join("src");
+        let mut lib = String::new();
+        self.write_mods(&mut lib, root);
+        files.insert(src.join("lib.rs"), lib);
+
+        for (name, module) in &root.children {
+            self.emit_module(&src.join(name), module, files);
+        }
+    }
+
+    fn emit_module(&self, path: &Path, module: &Module, files: &mut IndexMap<PathBuf, String>) {
+        let mut f = String::new();
+        writeln!(f, "{}", translator::prelude()).unwrap();
+        self.write_mods(&mut f, module);
+        if let Some((file_path, unit)) = module.unit {
+            if !module.children.is_empty() {
+                writeln!(f).unwrap();
+            }
+            writeln!(f, "// @generated from {:?}", file_path.source_path()).unwrap();
+            writeln!(f).unwrap();
+            self.write_imports(&mut f, unit);
+            write!(f, "{}", unit).unwrap();
+        }
+
+        let mut file_path = path.to_path_buf();
+        file_path.set_extension("rs");
+        log::info!("=> {}", file_path.display());
+        files.insert(file_path, f);
+
+        for (name, child) in &module.children {
+            self.emit_module(&path.join(name), child, files);
+        }
+    }
+
+    /// Declares the children of `module`, behind their feature if they
+    /// have items.
+    fn write_mods(&self, f: &mut String, module: &Module) {
+        for (name, child) in &module.children {
+            if let (true, Some((_, unit))) = (self.header_features, child.unit) {
+                writeln!(f, "#[cfg(feature = {:?})]", self.feature(unit.id)).unwrap();
+            }
+            writeln!(f, "pub mod {};", name).unwrap();
+        }
+    }
+
+    /// Name of the feature gating a unit's module, ie. `um-wintrust`
+    fn feature(&self, id: FileId) -> String {
+        self.mod_paths[&id].join("-")
+    }
+
+    /// Adds the features of the units below `module` in crate `name` to
+    /// `features`. Each enables the features of the units it imports from,
+    /// and the feature of its parent module, if any.
+    fn collect_features(
+        &self,
+        name: &str,
+        module: &Module,
+        parent: Option<&str>,
+        features: &mut BTreeMap<String, BTreeSet<String>>,
+    ) {
+        for child in module.children.values() {
+            let feature = match child.unit {
+                Some((_, unit)) => {
+                    let feature = self.feature(unit.id);
+                    let mut enables: BTreeSet<_> = parent.into_iter().map(String::from).collect();
+                    for (_, owner) in self.symbols.imports(unit) {
+                        if !self.mod_paths.contains_key(&owner) {
+                            continue;
+                        }
+                        let dep = self.feature(owner);
+                        match self.crates.get(&owner) {
+                            Some(krate) if krate != name => {
+                                enables.insert(format!("{}/{}", krate, dep));
+                            }
+                            _ if dep != feature => {
+                                enables.insert(dep);
+                            }
+                            _ => {}
+                        }
+                    }
+                    features.insert(feature.clone(), enables);
+                    Some(feature)
+                }
+                None => None,
+            };
+            let parent = feature.as_deref().or(parent);
+            self.collect_features(name, child, parent, features);
+        }
+    }
+
+    /// Returns the crates `units` need, either because they include one of
+    /// their headers, or because they use one of their items.
+    fn crate_dependencies<'a>(
+        &'a self,
+        parser: &Parser,
+        name: &str,
+        units: &[(&FilePath, &rg::Unit)],
+    ) -> BTreeSet<&'a str> {
+        let mut deps = BTreeSet::new();
+        for (_, unit) in units {
+            for id in referenced_units(parser, &self.symbols, unit) {
+                if let Some(dep) = self.crates.get(&id) {
+                    if dep != name {
+                        deps.insert(dep.as_str());
+                    }
+                }
+            }
+        }
+        deps
+    }
+
+    /// Writes one `use` per module `unit` refers to, ie.
+    /// `use crate::shared::basetsd::{DWORD, HANDLE};`, or
+    /// `use shared_sys::basetsd::{DWORD, HANDLE};` from another crate of
+    /// a workspace.
+    fn write_imports(&self, f: &mut String, unit: &rg::Unit) {
+        let krate = self.crates.get(&unit.id);
+        let mut imports: BTreeMap<(String, &[String]), BTreeSet<String>> = BTreeMap::new();
+        for (name, owner) in self.symbols.imports(unit) {
+            if let Some(mod_path) = self.mod_paths.get(&owner) {
+                let root = match self.crates.get(&owner) {
+                    Some(dep) if Some(dep) != krate => dep.replace('-', "_"),
+                    _ => "crate".to_string(),
+                };
+                imports
+                    .entry((root, &mod_path[..]))
+                    .or_default()
+                    .insert(rg::Identifier::name(name).to_string());
+            }
+        }
+
+        for ((root, mod_path), names) in &imports {
+            let names: Vec<_> = names.iter().map(|s| s.as_str()).collect();
+            match &names[..] {
+                [name] => writeln!(f, "use {}::{}::{};", root, mod_path.join("::"), name),
+                names => writeln!(
+                    f,
+                    "use {}::{}::{{{}}};",
+                    root,
+                    mod_path.join("::"),
+                    names.join(", ")
+                ),
+            }
+            .unwrap();
+        }
+        if !imports.is_empty() {
+            writeln!(f).unwrap();
+        }
+    }
+}
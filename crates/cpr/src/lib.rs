@@ -0,0 +1,19 @@
+//! Parses C header files and generates Rust bindings for them.
+//!
+//! See `Builder` for using cpr from a build script.
+
+#![allow(dead_code)]
+
+mod builder;
+pub mod config;
+pub mod devenv;
+pub mod frontend;
+pub mod layout;
+pub mod translator;
+
+pub use builder::{Bindings, Builder, Error, FileDiff};
+
+#[cfg(test)]
+mod test_builder;
+#[cfg(test)]
+mod test_config;
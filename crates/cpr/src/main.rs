@@ -1,19 +1,44 @@
-#![allow(dead_code)]
-
-mod devenv;
-mod frontend;
-mod translator;
-
 use argh::*;
-use frontend::{grammar::Include, Parser, SourceDir};
+use cpr::{
+    config::{Header, Project, Target},
+    frontend::graph::{GraphFormat, IncludeGraph},
+    layout::Layout,
+    translator,
+};
 use std::{error::Error, path::PathBuf};
 
 #[derive(FromArgs)]
-/// Parse a C header file and its includes and dump info about it
+/// Parse C header files and generate Rust bindings for them
 struct Args {
-    /// A C header file to parse
+    #[argh(subcommand)]
+    command: Command,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Generate(GenerateArgs),
+    Graph(GraphArgs),
+}
+
+#[derive(FromArgs)]
+/// Parse a C header file and its includes, and generate a crate with bindings
+#[argh(subcommand, name = "generate")]
+struct GenerateArgs {
+    /// C header files to parse into one crate, instead of the targets of a
+    /// configuration file. 'PKG=PATH' classifies a header as PKG instead of
+    /// the name of its directory, '<name.h>' is looked up in include dirs
     #[argh(positional)]
-    file: PathBuf,
+    files: Vec<Header>,
+
+    /// configuration file, defaults to 'cpr.toml', or the
+    /// [package.metadata.cpr] table of 'Cargo.toml', in the current directory
+    #[argh(option)]
+    config: Option<PathBuf>,
+
+    /// only generate the configured target with this name (can be repeated)
+    #[argh(option)]
+    target: Vec<String>,
 
     /// windows 10 Kit include dir, something like:
     /// 'C:\Program Files (x86)\Windows Kits\10\Include\10.0.18362.0'
@@ -31,166 +56,249 @@ struct Args {
 
     /// path of the crate to generate
     #[argh(option, short = 'o')]
-    output: PathBuf,
+    output: Option<PathBuf>,
+
+    /// how to arrange generated items: 'tree' (one module per header,
+    /// the default), 'flat' (everything in lib.rs) or 'workspace' (one
+    /// crate per include dir, ie. 'um-sys')
+    #[argh(option)]
+    layout: Option<Layout>,
+
+    /// gate each header's module behind a cargo feature of the same name
+    #[argh(switch)]
+    header_features: bool,
+
+    /// don't write anything, but fail if the output is out of date, showing
+    /// how it differs from what would be generated
+    #[argh(switch)]
+    check: bool,
+
+    /// write a Makefile-style list of every header that was read
+    #[argh(option)]
+    depfile: Option<PathBuf>,
 
     /// target architecture
     #[argh(option)]
     arch: Option<translator::Arch>,
 }
 
+#[derive(FromArgs)]
+/// Parse a C header file and its includes, and print the include graph
+#[argh(subcommand, name = "graph")]
+struct GraphArgs {
+    /// C header files to parse, instead of the target of a configuration
+    /// file, see `cpr generate --help`
+    #[argh(positional)]
+    files: Vec<Header>,
+
+    /// configuration file, see `cpr generate --help`
+    #[argh(option)]
+    config: Option<PathBuf>,
+
+    /// name of the configured target to graph, defaults to the first one
+    #[argh(option)]
+    target: Option<String>,
+
+    /// windows 10 Kit include dir, see `cpr generate --help`
+    #[argh(option)]
+    kits_path: Option<PathBuf>,
+
+    /// msvc include dir, see `cpr generate --help`
+    #[argh(option)]
+    msvc_path: Option<PathBuf>,
+
+    /// predefine a name
+    #[argh(option, short = 'D')]
+    defines: Vec<String>,
+
+    /// target architecture
+    #[argh(option)]
+    arch: Option<translator::Arch>,
+
+    /// output format: 'dot' (default) or 'json'
+    #[argh(option, default = "GraphFormat::Dot")]
+    format: GraphFormat,
+
+    /// file to write the graph to, instead of stdout
+    #[argh(option, short = 'o')]
+    output: Option<PathBuf>,
+}
+
+/// Command-line arguments that take precedence over the configuration file
+struct Overrides {
+    files: Vec<Header>,
+    config: Option<PathBuf>,
+    targets: Vec<String>,
+    kits_path: Option<PathBuf>,
+    msvc_path: Option<PathBuf>,
+    defines: Vec<String>,
+    arch: Option<translator::Arch>,
+    output: Option<PathBuf>,
+    layout: Option<Layout>,
+    header_features: bool,
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Args = argh::from_env();
 
-    let kits = args
-        .kits_path
-        .or_else(devenv::get_kits_path)
-        .expect("Windows 10 Kit include path should be autodetected or specified with --kits-path");
-
-    let msvc_path = args
-        .msvc_path
-        .or_else(devenv::get_msvc_path)
-        .expect("MSVC include path should be autodetected or specified with --msvc-path");
+    match args.command {
+        Command::Generate(args) => generate(args),
+        Command::Graph(args) => graph(args),
+    }
+}
 
-    let system_dirs = vec![
-        SourceDir {
-            pkg: "ucrt".into(),
-            path: kits.join("ucrt"),
+/// Returns the targets to process: the ones from the configuration file
+/// (or a single one for `files`), with command-line arguments applied.
+fn targets(o: Overrides) -> Result<Vec<Target>, Box<dyn Error>> {
+    let project = match (&o.config, o.files.first()) {
+        (Some(path), _) => Project::from_path(path)?,
+        (None, Some(file)) => Project {
+            targets: vec![Target {
+                name: file.stem().ok_or("invalid header path")?,
+                ..Default::default()
+            }],
         },
-        SourceDir {
-            pkg: "shared".into(),
-            path: kits.join("shared"),
-        },
-        SourceDir {
-            pkg: "um".into(),
-            path: kits.join("um"),
-        },
-        SourceDir {
-            pkg: "km".into(),
-            path: kits.join("km"),
-        },
-        SourceDir {
-            pkg: "vc".into(),
-            path: msvc_path,
-        },
-    ];
+        (None, None) => Project::find(&std::env::current_dir()?)?.ok_or(
+            "no header given, and no cpr.toml or [package.metadata.cpr] found in current directory",
+        )?,
+    };
 
-    let arch = args.arch.unwrap_or_default();
+    for name in &o.targets {
+        if !project.targets.iter().any(|t| &t.name == name) {
+            return Err(format!("no target named {:?}", name).into());
+        }
+    }
+    let mut targets: Vec<_> = project
+        .targets
+        .into_iter()
+        .filter(|t| o.targets.is_empty() || o.targets.contains(&t.name))
+        .collect();
+    if o.output.is_some() && targets.len() > 1 {
+        return Err("--output can only be used with a single target".into());
+    }
 
-    let mut ctx = frontend::Context::new();
-    match arch {
-        translator::Arch::X86 => {
-            for &s in &["_X86", "_M_X86", "_WIN32"] {
-                ctx.simple_define(s);
-            }
+    for t in &mut targets {
+        if !o.files.is_empty() {
+            t.headers = o.files.clone();
         }
-        translator::Arch::X86_64 => {
-            for &s in &["_AMD64_", "_M_AMD64", "_WIN32", "_WIN64"] {
-                ctx.simple_define(s);
-            }
+        if let Some(path) = &o.kits_path {
+            t.kits_path = Some(path.clone());
+        }
+        if let Some(path) = &o.msvc_path {
+            t.msvc_path = Some(path.clone());
+        }
+        t.defines.extend(o.defines.iter().cloned());
+        if let Some(arch) = o.arch {
+            t.arches = vec![arch];
+        }
+        if let Some(output) = &o.output {
+            t.output = Some(output.clone());
+        }
+        if let Some(layout) = o.layout {
+            t.layout = layout;
+        }
+        if o.header_features {
+            t.header_features = true;
         }
     }
+    Ok(targets)
+}
 
-    log::info!("System dirs:");
-    for sd in &system_dirs {
-        log::info!("{} | {}", sd.pkg, sd.path.display());
+fn graph(args: GraphArgs) -> Result<(), Box<dyn Error>> {
+    let targets = targets(Overrides {
+        files: args.files,
+        config: args.config,
+        targets: args.target.into_iter().collect(),
+        kits_path: args.kits_path,
+        msvc_path: args.msvc_path,
+        defines: args.defines,
+        arch: args.arch,
+        output: None,
+        layout: None,
+        header_features: false,
+    })?;
+    let target = targets.first().ok_or("no targets configured")?;
+    let parser = target.builder(target.arches()[0])?.parse()?;
+
+    let out = IncludeGraph::new(&parser).write(args.format)?;
+    match &args.output {
+        Some(path) => std::fs::write(path, out)?,
+        None => print!("{}", out),
     }
 
-    let provider = frontend::FileSourceProvider::new(system_dirs);
-
-    let root_file = &args.file;
-    let root_parent = root_file.parent().unwrap();
-    let root_source_dir = SourceDir {
-        pkg: root_parent
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string(),
-        path: root_parent.to_path_buf(),
-    };
-    let root_include = Include::Quoted(PathBuf::from(root_file.file_name().unwrap()));
-
-    let mut parser = Parser::new(Box::new(provider), ctx, Env::with_msvc());
-    let root_id = parser
-        .provider
-        .resolve(&mut parser.idgen, &root_source_dir, &root_include)?;
-
-    parser.parse_file(root_id)?;
-    log::info!("Done parsing!");
-
-    let config = translator::Config { arch };
-
-    use std::{fs, io::Write};
-    let manifest_path = args.output.join("Cargo.toml");
-    fs::create_dir_all(manifest_path.parent().unwrap())?;
-    std::fs::write(
-        &manifest_path,
-        format!(
-            r#"
-[package]
-name = "{crate_name}"
-version = "0.1.0"
-authors = []
-edition = "2018"
-
-# workaround for cpr itself being a workspace
-[workspace]
-"#,
-            crate_name = root_source_dir.pkg
-        ),
-    )?;
-
-    let top_level_path = args.output.join("src").join("lib.rs");
-    fs::create_dir_all(top_level_path.parent().unwrap())?;
-    let mut top_level = fs::File::create(&top_level_path)?;
-
-    for incl in &parser.ordered_files {
-        let unit = parser.units.get(incl).unwrap();
-        let file_info = parser.provider.info(unit.id).unwrap();
-
-        if unit.declarations.is_empty() {
-            println!("{} | skipping (no decls)", file_info.path);
-            continue;
+    Ok(())
+}
+
+fn generate(args: GenerateArgs) -> Result<(), Box<dyn Error>> {
+    let targets = targets(Overrides {
+        files: args.files,
+        config: args.config,
+        targets: args.target,
+        kits_path: args.kits_path,
+        msvc_path: args.msvc_path,
+        defines: args.defines,
+        arch: args.arch,
+        output: args.output,
+        layout: args.layout,
+        header_features: args.header_features,
+    })?;
+
+    let mut depfile = String::new();
+    let (mut checked, mut outdated) = (0, 0);
+    for target in &targets {
+        for arch in target.arches() {
+            let output = target
+                .output_dir(arch)
+                .ok_or_else(|| format!("no output specified for target {:?}", target.name))?;
+            let bindings = target.builder(arch)?.generate()?;
+            for diag in bindings.diagnostics() {
+                eprintln!("warning: {}", diag);
+            }
+
+            if args.check {
+                let diffs = bindings.check(&output)?;
+                for diff in &diffs {
+                    print!("{}", diff.diff);
+                }
+                checked += bindings.files().count();
+                outdated += diffs.len();
+                continue;
+            }
+
+            bindings.write_to(&output)?;
+            for (path, _) in bindings.files() {
+                println!("{}", output.join(path).display());
+            }
+
+            // a workspace has no `src/lib.rs`
+            let out_path = match bindings.file("src/lib.rs") {
+                Some(_) => output.join("src").join("lib.rs"),
+                None => output.join("Cargo.toml"),
+            };
+            depfile.push_str(&bindings.dependencies().to_makefile(&out_path));
         }
+    }
+
+    if args.check {
+        if outdated > 0 {
+            return Err(format!(
+                "{} of {} generated files are out of date, run `cpr generate` to update them",
+                outdated, checked
+            )
+            .into());
+        }
+        eprintln!("{} generated files are up to date", checked);
+        return Ok(());
+    }
 
-        let trans_unit = translator::translate_unit(
-            &config,
-            parser.provider.as_ref(),
-            unit.id,
-            &unit.declarations,
-        );
-        let pkg_components = file_info.path.pkg_components();
-        let stem = pkg_components.last().unwrap();
-
-        // TODO: this is all kinds of wrong
-        writeln!(top_level, "pub mod {};", stem)?;
-        writeln!(top_level, "pub use {}::*;", stem)?;
-
-        let mut out_path = args.output.join("src").join(stem);
-        out_path.set_extension("rs");
-
-        fs::create_dir_all(out_path.parent().unwrap())?;
-        let mut f = fs::File::create(&out_path)?;
-        writeln!(f, "{}", translator::prelude())?;
-        writeln!(f, "// @generated from {:?}", file_info.path.source_path())?;
-        writeln!(f)?;
-
-        writeln!(f, "pub use super::*;")?;
-        write!(f, "{}", trans_unit)?;
-        println!(
-            "{} | ({} C => {} Rust) => {}",
-            file_info.path,
-            unit.declarations.len(),
-            trans_unit.toplevels.len(),
-            out_path.display()
-        );
+    if let Some(path) = &args.depfile {
+        std::fs::write(path, depfile)?;
     }
 
     Ok(())
 }
 
 use ctor::ctor;
-use lang_c::env::Env;
 
 #[ctor]
 fn install_extensions() {
@@ -0,0 +1,881 @@
+use super::*;
+use crate::frontend::features::{CompilerProfile, FeatureTest};
+use crate::frontend::{
+    self, grammar::Include, FileId, FileInfo, IdGenerator, SourceDir, SourceProvider,
+};
+use crate::layout::{module_name, Layout};
+use crate::translator::test_translator::{provider, TestSourceProvider};
+use crate::translator::{Arch, CTypes, DataModel};
+use indoc::indoc;
+use std::path::Path;
+
+#[test]
+fn generate() {
+    let bindings = Builder::new()
+        .header("root.h")
+        .crate_name("foo-sys")
+        .define("FOO_VALUE=42")
+        .define("FOO_ENABLED")
+        .source_provider(provider(&[
+            (
+                "root.h",
+                "#if FOO_ENABLED\n#include \"foo.h\"\n#endif\nint FooValue(int v[FOO_VALUE]);\n",
+            ),
+            ("foo.h", "typedef unsigned long FOO;\n"),
+        ]))
+        .generate()
+        .unwrap();
+
+    let paths: Vec<_> = bindings.files().map(|(p, _)| p).collect();
+    assert_eq!(
+        paths,
+        vec![
+            Path::new("Cargo.toml"),
+            Path::new("src/lib.rs"),
+            Path::new("src/root.rs"),
+            Path::new("src/root/root.rs"),
+            Path::new("src/root/foo.rs"),
+        ]
+    );
+    assert!(bindings
+        .file("Cargo.toml")
+        .unwrap()
+        .contains(r#"name = "foo-sys""#));
+    assert!(bindings
+        .file("src/root/foo.rs")
+        .unwrap()
+        .contains("pub type FOO"));
+    assert_eq!(bindings.dependencies().paths.len(), 2);
+}
+
+#[test]
+fn multiple_roots() {
+    let bindings = Builder::new()
+        .header("a.h")
+        .header_with_pkg("sub/b.h", "bee")
+        .header("<c.h>")
+        .source_provider(provider(&[
+            ("a.h", "#define A_VALUE 3\ntypedef int A_INT;\n"),
+            ("b.h", "#if A_VALUE == 3\ntypedef A_INT B_INT;\n#endif\n"),
+            ("c.h", "typedef B_INT C_INT;\n"),
+        ]))
+        .generate()
+        .unwrap();
+
+    let paths: Vec<_> = bindings.files().map(|(p, _)| p).collect();
+    assert_eq!(
+        paths,
+        vec![
+            Path::new("Cargo.toml"),
+            Path::new("src/lib.rs"),
+            Path::new("src/root.rs"),
+            Path::new("src/root/a.rs"),
+            Path::new("src/root/c.rs"),
+            Path::new("src/bee.rs"),
+            Path::new("src/bee/b.rs"),
+        ]
+    );
+    // the first root names the crate
+    assert!(bindings
+        .file("Cargo.toml")
+        .unwrap()
+        .contains(r#"name = "root""#));
+
+    // defines carry over from one root to the next
+    let b = bindings.file("src/bee/b.rs").unwrap();
+    assert!(b.contains("use crate::root::a::A_INT;\n"));
+    assert!(b.contains("pub type B_INT = A_INT;"));
+    let c = bindings.file("src/root/c.rs").unwrap();
+    assert!(c.contains("use crate::bee::b::B_INT;\n"));
+    assert_eq!(bindings.dependencies().paths.len(), 3);
+}
+
+#[test]
+fn check() {
+    let generate = |value: &str| {
+        Builder::new()
+            .header("root.h")
+            .source_provider(provider(&[
+                ("root.h", "#include \"foo.h\"\n"),
+                (
+                    "foo.h",
+                    &format!("#define FOO {}\ntypedef int FOO_T;\n", value),
+                ),
+            ]))
+            .generate()
+            .unwrap()
+    };
+
+    let dir = std::env::temp_dir().join(format!("cpr-check-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let bindings = generate("1");
+    bindings.write_to(&dir).unwrap();
+    assert!(bindings.check(&dir).unwrap().is_empty());
+
+    let diffs = generate("2").check(&dir).unwrap();
+    assert_eq!(diffs.len(), 1);
+    assert_eq!(diffs[0].path, Path::new("src/root/foo.rs"));
+    assert!(diffs[0].diff.contains("-pub const FOO: "));
+    assert!(diffs[0].diff.contains("+pub const FOO: "));
+
+    std::fs::remove_file(dir.join("Cargo.toml")).unwrap();
+    let diffs = bindings.check(&dir).unwrap();
+    assert_eq!(diffs.len(), 1);
+    assert!(diffs[0].diff.starts_with("--- /dev/null\n"));
+
+    // modules left over from an earlier generation are drift too, while
+    // hand-written files next to `src` and build output are left alone
+    std::fs::write(dir.join("Cargo.toml"), bindings.file("Cargo.toml").unwrap()).unwrap();
+    std::fs::create_dir_all(dir.join("src/old")).unwrap();
+    std::fs::write(dir.join("src/old/bar.rs"), "pub type BAR = i32;\n").unwrap();
+    std::fs::write(dir.join("src/README.md"), "").unwrap();
+    std::fs::write(dir.join("build.rs"), "fn main() {}\n").unwrap();
+    std::fs::create_dir_all(dir.join("tests")).unwrap();
+    std::fs::write(dir.join("tests/link.rs"), "").unwrap();
+    std::fs::create_dir_all(dir.join("target/debug/build")).unwrap();
+    std::fs::write(dir.join("target/debug/build/out.rs"), "").unwrap();
+    let diffs = bindings.check(&dir).unwrap();
+    assert_eq!(diffs.len(), 1);
+    assert_eq!(diffs[0].path, Path::new("src/old/bar.rs"));
+    assert!(diffs[0].diff.contains("+++ /dev/null\n"));
+    assert!(diffs[0].diff.contains("-pub type BAR = i32;\n"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn errors() {
+    assert!(matches!(Builder::new().generate(), Err(Error::NoRoot)));
+    assert!(matches!(
+        Builder::new()
+            .header("root.h")
+            .define("=1")
+            .source_provider(provider(&[("root.h", "")]))
+            .parse(),
+        Err(Error::InvalidDefine(_))
+    ));
+}
+
+/// Classifies `root*.h` as `um`, and other headers as `shared`, like
+/// include directories of the Windows SDK would
+struct PackageSourceProvider(Box<TestSourceProvider>);
+
+impl SourceProvider for PackageSourceProvider {
+    fn resolve(
+        &mut self,
+        idgen: &mut IdGenerator,
+        _working_dir: &SourceDir,
+        include: &Include,
+    ) -> Result<FileId, frontend::Error> {
+        let path: &Path = include.as_ref();
+        let pkg = if path.to_string_lossy().starts_with("root") {
+            "um"
+        } else {
+            "shared"
+        };
+        let dir = SourceDir {
+            pkg: pkg.into(),
+            path: pkg.into(),
+        };
+        self.0.resolve(idgen, &dir, include)
+    }
+    fn info(&self, id: FileId) -> Option<&FileInfo> {
+        self.0.info(id)
+    }
+    fn read(&self, id: FileId) -> Result<String, frontend::Error> {
+        self.0.read(id)
+    }
+    fn exists(&self, working_dir: &SourceDir, include: &Include) -> bool {
+        self.0.exists(working_dir, include)
+    }
+}
+
+#[test]
+fn type_overrides() {
+    let bindings = Builder::new()
+        .header("root.h")
+        .type_override("GUID", "::winapi::shared::guiddef::GUID")
+        .type_override("struct_POINT", "crate::Point")
+        .source_provider(provider(&[
+            (
+                "root.h",
+                indoc!(
+                    "
+                    #include \"guid.h\"
+                    typedef GUID IID;
+                    struct POINT { long x, y; };
+                    int Compare(const GUID *a, struct POINT *p);
+                    "
+                ),
+            ),
+            (
+                "guid.h",
+                "typedef struct _GUID { unsigned long Data1; } GUID;\n",
+            ),
+        ]))
+        .generate()
+        .unwrap();
+
+    let root = bindings.file("src/root/root.rs").unwrap();
+    assert!(root.contains("pub type IID = ::winapi::shared::guiddef::GUID;"));
+    assert!(root.contains("(a: *const ::winapi::shared::guiddef::GUID, p: *mut crate::Point)"));
+    assert!(!root.contains("struct_POINT"));
+    assert!(!root.contains("use crate::root::guid"));
+    // only the typedef is overridden, not the struct it names
+    let guid = bindings.file("src/root/guid.rs").unwrap();
+    assert!(!guid.contains("pub type GUID"));
+    assert!(guid.contains("pub struct struct__GUID"));
+}
+
+#[test]
+fn compiler_profile() {
+    let source = indoc!(
+        "
+        #if __has_attribute(packed)
+        typedef int PACKED;
+        #endif
+        #if __has_include(\"root.h\")
+        typedef int FOUND;
+        #endif
+        "
+    );
+    let generate = |builder: Builder| {
+        builder
+            .header("root.h")
+            // resolves everything, and answers `__has_include` from that
+            .source_provider(Box::new(PackageSourceProvider(provider(&[(
+                "root.h", source,
+            )]))))
+            .generate()
+            .unwrap()
+            .file("src/um/root.rs")
+            .unwrap()
+            .to_string()
+    };
+
+    let root = generate(Builder::new());
+    assert!(!root.contains("PACKED"));
+    assert!(root.contains("pub type FOUND"));
+
+    let mut profile = CompilerProfile::msvc();
+    profile.set(FeatureTest::Attribute, "packed", 1);
+    let root = generate(Builder::new().compiler_profile(profile));
+    assert!(root.contains("pub type PACKED"));
+}
+
+fn filter_provider() -> Box<TestSourceProvider> {
+    provider(&[
+        (
+            "root.h",
+            indoc!(
+                "
+                #include \"types.h\"
+                typedef struct Callbacks { START_CB Start; int Flags; } Callbacks;
+                int StartVirtualizing(const Callbacks *cb, HANDLE_T *handle);
+                int Unrelated(UNRELATED u);
+                #define SOME_FLAG 4
+                "
+            ),
+        ),
+        (
+            "types.h",
+            indoc!(
+                "
+                typedef void *HANDLE_T;
+                typedef int (*START_CB)(HANDLE_T h);
+                typedef long UNRELATED;
+                "
+            ),
+        ),
+    ])
+}
+
+fn item_names(bindings: &Bindings) -> Vec<String> {
+    let mut names = Vec::new();
+    for (path, contents) in bindings.files() {
+        if path.extension().map(|e| e == "rs").unwrap_or(false) {
+            for line in contents.lines() {
+                let line = line.trim_start();
+                for prefix in &["pub type ", "pub struct ", "pub fn ", "pub const "] {
+                    if let Some(rest) = line.strip_prefix(prefix) {
+                        let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_'));
+                        names.push(rest[..end.unwrap_or(rest.len())].to_string());
+                    }
+                }
+            }
+        }
+    }
+    names.sort();
+    names
+}
+
+#[test]
+fn allowlist_closure() {
+    let bindings = Builder::new()
+        .header("root.h")
+        .source_provider(filter_provider())
+        .allow_function("Start.*")
+        .generate()
+        .unwrap();
+    assert_eq!(
+        item_names(&bindings),
+        vec![
+            "Callbacks",
+            "HANDLE_T",
+            "START_CB",
+            "StartVirtualizing",
+            "struct_Callbacks"
+        ]
+    );
+
+    let bindings = Builder::new()
+        .header("root.h")
+        .source_provider(filter_provider())
+        .allow_header("root/types\\.h")
+        .allow_constant("SOME_.*")
+        .generate()
+        .unwrap();
+    assert_eq!(
+        item_names(&bindings),
+        vec!["HANDLE_T", "SOME_FLAG", "START_CB", "UNRELATED"]
+    );
+}
+
+#[test]
+fn blocklist() {
+    let bindings = Builder::new()
+        .header("root.h")
+        .source_provider(filter_provider())
+        .allow_function("Start.*")
+        .block_type("START_CB")
+        .generate()
+        .unwrap();
+    assert_eq!(
+        item_names(&bindings),
+        vec![
+            "Callbacks",
+            "HANDLE_T",
+            "StartVirtualizing",
+            "struct_Callbacks"
+        ]
+    );
+
+    let bindings = Builder::new()
+        .header("root.h")
+        .source_provider(filter_provider())
+        .block_function("Unrelated")
+        .block_header(".*/types\\.h")
+        .generate()
+        .unwrap();
+    assert_eq!(
+        item_names(&bindings),
+        vec![
+            "Callbacks",
+            "SOME_FLAG",
+            "StartVirtualizing",
+            "struct_Callbacks"
+        ]
+    );
+    assert!(bindings.file("src/root/types.rs").is_none());
+
+    assert!(matches!(
+        Builder::new()
+            .header("root.h")
+            .source_provider(filter_provider())
+            .allow_type("(")
+            .generate(),
+        Err(Error::InvalidPattern(_))
+    ));
+}
+
+#[test]
+fn module_tree() {
+    let bindings = Builder::new()
+        .header("root.h")
+        .source_provider(provider(&[
+            (
+                "root.h",
+                indoc!(
+                    "
+                    #include \"WinTrust.h\"
+                    #include \"wintrust.hpp\"
+                    #include \"sub/type.h\"
+                    #include \"sub.h\"
+                    "
+                ),
+            ),
+            ("WinTrust.h", "typedef int A;\n"),
+            ("wintrust.hpp", "typedef int B;\n"),
+            ("sub/type.h", "typedef int C;\n"),
+            ("sub.h", "typedef int D;\n"),
+        ]))
+        .generate()
+        .unwrap();
+
+    let paths: Vec<_> = bindings.files().map(|(p, _)| p).collect();
+    assert_eq!(
+        paths,
+        vec![
+            Path::new("Cargo.toml"),
+            Path::new("src/lib.rs"),
+            Path::new("src/root.rs"),
+            Path::new("src/root/wintrust.rs"),
+            Path::new("src/root/wintrust_2.rs"),
+            Path::new("src/root/sub.rs"),
+            Path::new("src/root/sub/type_.rs"),
+        ]
+    );
+    assert_eq!(bindings.file("src/lib.rs").unwrap(), "pub mod root;\n");
+
+    let root = bindings.file("src/root.rs").unwrap();
+    assert!(root.contains("pub mod wintrust;\npub mod wintrust_2;\n"));
+    assert!(!root.contains("pub type"));
+
+    // `sub.h` and `sub/type.h` share a module
+    let sub = bindings.file("src/root/sub.rs").unwrap();
+    assert!(sub.contains("pub mod type_;\n"));
+    assert!(sub.contains("pub type D = "));
+}
+
+#[test]
+fn explicit_imports() {
+    let bindings = Builder::new()
+        .header("root.h")
+        .source_provider(provider(&[
+            (
+                "root.h",
+                indoc!(
+                    "
+                    #include \"basetsd.h\"
+                    #include \"sub/handle.h\"
+                    typedef struct S { DWORD a; HANDLE h; WORD w; } S;
+                    DWORD GetS(S *s, DWORD *out);
+                    "
+                ),
+            ),
+            (
+                "basetsd.h",
+                "typedef unsigned long DWORD;\ntypedef unsigned short WORD;\n",
+            ),
+            (
+                "sub/handle.h",
+                "typedef void *HANDLE;\ntypedef DWORD LONG_HANDLE;\n",
+            ),
+        ]))
+        .generate()
+        .unwrap();
+
+    let root = bindings.file("src/root/root.rs").unwrap();
+    assert!(root.contains(indoc!(
+        "
+        use crate::root::basetsd::{DWORD, WORD};
+        use crate::root::sub::handle::HANDLE;
+
+        "
+    )));
+    assert!(!root.contains("use crate::root::root"));
+    assert!(!root.contains("use crate::*"));
+
+    // names that don't resolve to a unit, ie. builtins, aren't imported
+    let basetsd = bindings.file("src/root/basetsd.rs").unwrap();
+    assert!(!basetsd.contains("use "));
+
+    // `sub/handle.h` uses `DWORD` without including `basetsd.h`
+    let handle = bindings.file("src/root/sub/handle.rs").unwrap();
+    assert!(handle.contains("use crate::root::basetsd::DWORD;\n"));
+}
+
+#[test]
+fn flat_layout() {
+    let bindings = Builder::new()
+        .header("root.h")
+        .layout(Layout::Flat)
+        .source_provider(provider(&[
+            ("root.h", "#include \"foo.h\"\ntypedef FOO BAR;\n"),
+            ("foo.h", "typedef int FOO;\n"),
+        ]))
+        .generate()
+        .unwrap();
+
+    let paths: Vec<_> = bindings.files().map(|(p, _)| p).collect();
+    assert_eq!(
+        paths,
+        vec![Path::new("Cargo.toml"), Path::new("src/lib.rs")]
+    );
+    let lib = bindings.file("src/lib.rs").unwrap();
+    assert!(lib.starts_with(crate::translator::prelude()));
+    assert!(lib.contains("pub type FOO = "));
+    assert!(lib.contains("pub type BAR = FOO;"));
+}
+
+#[test]
+fn workspace_layout() {
+    let bindings = Builder::new()
+        .header("<root.h>")
+        .layout(Layout::Workspace)
+        .source_provider(Box::new(PackageSourceProvider(provider(&[
+            (
+                "root.h",
+                "#include <basetsd.h>\n#include <sub/handle.h>\nDWORD GetHandle(HANDLE *h);\n",
+            ),
+            ("basetsd.h", "typedef unsigned long DWORD;\n"),
+            ("sub/handle.h", "typedef void *HANDLE;\n"),
+        ]))))
+        .generate()
+        .unwrap();
+
+    let paths: Vec<_> = bindings.files().map(|(p, _)| p).collect();
+    assert_eq!(
+        paths,
+        vec![
+            Path::new("Cargo.toml"),
+            Path::new("um-sys/Cargo.toml"),
+            Path::new("um-sys/src/lib.rs"),
+            Path::new("um-sys/src/root.rs"),
+            Path::new("shared-sys/Cargo.toml"),
+            Path::new("shared-sys/src/lib.rs"),
+            Path::new("shared-sys/src/basetsd.rs"),
+            Path::new("shared-sys/src/sub.rs"),
+            Path::new("shared-sys/src/sub/handle.rs"),
+        ]
+    );
+    assert_eq!(
+        bindings.file("Cargo.toml").unwrap(),
+        "[workspace]\nmembers = [\n    \"um-sys\",\n    \"shared-sys\",\n]\n"
+    );
+
+    let um = bindings.file("um-sys/Cargo.toml").unwrap();
+    assert!(um.contains("[dependencies]\nshared-sys = { path = \"../shared-sys\" }\n"));
+    assert!(!um.contains("[workspace]"));
+    assert!(!bindings
+        .file("shared-sys/Cargo.toml")
+        .unwrap()
+        .contains("[dependencies]"));
+
+    let root = bindings.file("um-sys/src/root.rs").unwrap();
+    assert!(
+        root.contains("use shared_sys::basetsd::DWORD;\nuse shared_sys::sub::handle::HANDLE;\n")
+    );
+    assert_eq!(
+        bindings.file("shared-sys/src/lib.rs").unwrap(),
+        "pub mod basetsd;\npub mod sub;\n"
+    );
+}
+
+#[test]
+fn workspace_cycles() {
+    let bindings = Builder::new()
+        .header("<root.h>")
+        .layout(Layout::Workspace)
+        .source_provider(Box::new(PackageSourceProvider(provider(&[
+            ("root.h", "#include <basetsd.h>\nDWORD GetValue(void);\n"),
+            ("roottypes.h", "typedef int ROOT;\n"),
+            ("basetsd.h", "#include <roottypes.h>\ntypedef ROOT DWORD;\n"),
+        ]))))
+        .generate()
+        .unwrap();
+
+    // cargo rejects cycles, so packages including each other are merged
+    let paths: Vec<_> = bindings.files().map(|(p, _)| p).collect();
+    assert_eq!(
+        paths,
+        vec![
+            Path::new("Cargo.toml"),
+            Path::new("um-shared-sys/Cargo.toml"),
+            Path::new("um-shared-sys/src/lib.rs"),
+            Path::new("um-shared-sys/src/um.rs"),
+            Path::new("um-shared-sys/src/um/root.rs"),
+            Path::new("um-shared-sys/src/um/roottypes.rs"),
+            Path::new("um-shared-sys/src/shared.rs"),
+            Path::new("um-shared-sys/src/shared/basetsd.rs"),
+        ]
+    );
+    assert!(!bindings
+        .file("um-shared-sys/Cargo.toml")
+        .unwrap()
+        .contains("[dependencies]"));
+    let root = bindings.file("um-shared-sys/src/um/root.rs").unwrap();
+    assert!(root.contains("use crate::shared::basetsd::DWORD;\n"));
+    let basetsd = bindings
+        .file("um-shared-sys/src/shared/basetsd.rs")
+        .unwrap();
+    assert!(basetsd.contains("use crate::um::roottypes::ROOT;\n"));
+}
+
+#[test]
+fn header_features() {
+    let bindings = Builder::new()
+        .header("root.h")
+        .header_features(true)
+        .source_provider(provider(&[
+            (
+                "root.h",
+                indoc!(
+                    "
+                    #include \"basetsd.h\"
+                    #include \"sub.h\"
+                    #include \"sub/handle.h\"
+                    DWORD GetHandle(HANDLE *h);
+                    "
+                ),
+            ),
+            ("basetsd.h", "typedef unsigned long DWORD;\n"),
+            ("sub.h", "typedef int SUB;\n"),
+            ("sub/handle.h", "typedef void *HANDLE;\n"),
+        ]))
+        .generate()
+        .unwrap();
+
+    assert!(bindings.file("Cargo.toml").unwrap().contains(indoc!(
+        r#"
+        [features]
+        root-basetsd = []
+        root-root = ["root-basetsd", "root-sub-handle"]
+        root-sub = []
+        root-sub-handle = ["root-sub"]
+        "#
+    )));
+    // modules without items of their own aren't gated
+    assert_eq!(bindings.file("src/lib.rs").unwrap(), "pub mod root;\n");
+    let root = bindings.file("src/root.rs").unwrap();
+    assert!(root.contains("#[cfg(feature = \"root-basetsd\")]\npub mod basetsd;\n"));
+    let sub = bindings.file("src/root/sub.rs").unwrap();
+    assert!(sub.contains("#[cfg(feature = \"root-sub-handle\")]\npub mod handle;\n"));
+
+    let bindings = Builder::new()
+        .header("<root.h>")
+        .layout(Layout::Workspace)
+        .header_features(true)
+        .source_provider(Box::new(PackageSourceProvider(provider(&[
+            (
+                "root.h",
+                "#include <basetsd.h>\n#include <sub/handle.h>\nDWORD GetHandle(HANDLE *h);\n",
+            ),
+            ("basetsd.h", "typedef unsigned long DWORD;\n"),
+            ("sub/handle.h", "typedef void *HANDLE;\n"),
+        ]))))
+        .generate()
+        .unwrap();
+
+    // features of other crates of the workspace are enabled through them
+    assert!(bindings
+        .file("um-sys/Cargo.toml")
+        .unwrap()
+        .contains("[features]\nroot = [\"shared-sys/basetsd\", \"shared-sys/sub-handle\"]\n"));
+    assert!(bindings
+        .file("shared-sys/Cargo.toml")
+        .unwrap()
+        .contains("[features]\nbasetsd = []\nsub-handle = []\n"));
+}
+
+#[test]
+fn bitfields() {
+    let bindings = Builder::new()
+        .header("root.h")
+        .source_provider(provider(&[
+            (
+                "root.h",
+                indoc!(
+                    "
+                    #include \"types.h\"
+                    typedef struct FLAGS {
+                        DWORD Valid : 1;
+                        LONG Delta : 4;
+                        DWORD : 0;
+                        BYTE Small : 4;
+                        BYTE : 2;
+                        BYTE Tiny : 2;
+                        DWORD Whole;
+                    } FLAGS;
+                    "
+                ),
+            ),
+            (
+                "types.h",
+                "typedef unsigned long DWORD;\ntypedef long LONG;\ntypedef unsigned char BYTE;\n",
+            ),
+        ]))
+        .generate()
+        .unwrap();
+
+    let root = bindings.file("src/root/root.rs").unwrap();
+    // MSVC packs same-sized bitfields together, and starts a new storage
+    // unit on a zero-width bitfield or a change of size
+    assert!(root.contains(indoc!(
+        "
+        pub struct struct_FLAGS {
+            _bitfield_1: u32,
+            _bitfield_2: u8,
+            Whole: DWORD,
+        }
+        "
+    )));
+    assert!(root.contains("pub fn Valid(&self) -> DWORD {"));
+    assert!(root.contains("(((self._bitfield_1 << 27) as i32) >> 28) as LONG"));
+    assert!(root.contains("((self._bitfield_2 >> 6) & 0x3) as BYTE"));
+    assert!(root.contains("pub fn set_Tiny(&mut self, value: BYTE) {"));
+    // types used by accessors only are imported too
+    assert!(root.contains("use crate::root::types::{BYTE, DWORD, LONG};"));
+}
+
+#[test]
+fn data_models() {
+    let source = indoc!(
+        "
+        typedef unsigned short wchar_t;
+        typedef struct SIZES {
+            long Long;
+            unsigned long ULong : 3;
+            long double LongDouble;
+            wchar_t Wide;
+            char Narrow;
+        } SIZES;
+        "
+    );
+    let generate = |builder: Builder| {
+        builder
+            .header("root.h")
+            .source_provider(provider(&[("root.h", source)]))
+            .generate()
+            .unwrap()
+            .file("src/root/root.rs")
+            .unwrap()
+            .to_string()
+    };
+
+    // exact types, whatever the host's `long` is
+    let root = generate(Builder::new());
+    assert!(root.contains("    Long: i32,\n    _bitfield_1: u32,\n    LongDouble: f64,\n"));
+    assert!(root.contains("    Wide: u16,\n    Narrow: i8,\n"));
+    let root = generate(Builder::new().arch(Arch::X86));
+    assert!(root.contains("    Long: i32,\n"));
+
+    let root = generate(Builder::new().data_model(DataModel::LP64));
+    assert!(root.contains("    Long: i64,\n    _bitfield_1: u64,\n    LongDouble: u128,\n"));
+    assert!(root.contains("    Wide: i32,\n"));
+
+    let root = generate(Builder::new().ctypes(CTypes::CoreFfi));
+    assert!(root.contains("    Long: ::core::ffi::c_long,\n    _bitfield_1: u32,\n"));
+    assert!(root.contains("pub fn ULong(&self) -> ::core::ffi::c_ulong {"));
+    assert!(root.contains("    Narrow: ::core::ffi::c_char,\n"));
+    let root = generate(Builder::new().ctypes(CTypes::StdOsRaw));
+    assert!(root.contains("    Long: ::std::os::raw::c_long,\n"));
+}
+
+#[test]
+fn array_typedef_params() {
+    let bindings = Builder::new()
+        .header("root.h")
+        .source_provider(provider(&[
+            ("root.h", "#include \"matrix.h\"\nvoid Fill(MATRIX m);\n"),
+            ("matrix.h", "typedef float MATRIX[4][4];\n"),
+        ]))
+        .generate()
+        .unwrap();
+
+    assert!(bindings
+        .file("src/root/matrix.rs")
+        .unwrap()
+        .contains("pub type MATRIX = [[f32; 4]; 4];"));
+    // like arrays, array typedefs decay to pointers when passed
+    assert!(bindings
+        .file("src/root/root.rs")
+        .unwrap()
+        .contains("pub fn Fill (m: *mut [f32; 4]);"));
+}
+
+#[test]
+fn function_typedef_pointers() {
+    let bindings = Builder::new()
+        .header("root.h")
+        .source_provider(provider(&[
+            (
+                "root.h",
+                "#include \"handler.h\"\ntypedef HANDLER *PHANDLER;\n",
+            ),
+            ("handler.h", "typedef void HANDLER(int code);\n"),
+        ]))
+        .generate()
+        .unwrap();
+
+    // Rust function pointers are already pointers
+    assert!(bindings
+        .file("src/root/root.rs")
+        .unwrap()
+        .contains("pub type PHANDLER = HANDLER;"));
+}
+
+#[test]
+fn module_names() {
+    assert_eq!(module_name("WinTrust"), "wintrust");
+    assert_eq!(module_name("d3d11_1"), "d3d11_1");
+    assert_eq!(module_name("my-lib"), "my_lib");
+    assert_eq!(module_name("3dfx"), "_3dfx");
+    assert_eq!(module_name("type"), "type_");
+}
+
+#[test]
+fn deduplicate() {
+    let bindings = Builder::new()
+        .header("root.h")
+        .source_provider(provider(&[
+            (
+                "root.h",
+                indoc!(
+                    "
+                    #include \"a.h\"
+                    #include \"b.h\"
+                    "
+                ),
+            ),
+            (
+                "a.h",
+                indoc!(
+                    "
+                    typedef unsigned long DWORD;
+                    typedef struct Opaque *POPAQUE;
+                    typedef int CONFLICT;
+                    DWORD GetA(void);
+                    "
+                ),
+            ),
+            (
+                "b.h",
+                indoc!(
+                    "
+                    typedef unsigned long DWORD;
+                    struct Opaque { DWORD size; };
+                    typedef short CONFLICT;
+                    DWORD GetA(void);
+                    "
+                ),
+            ),
+        ]))
+        .generate()
+        .unwrap();
+
+    let a = bindings.file("src/root/a.rs").unwrap();
+    let b = bindings.file("src/root/b.rs").unwrap();
+
+    // identical declarations stay in the first unit
+    assert!(a.contains("pub type DWORD = "));
+    assert!(!b.contains("pub type DWORD = "));
+    assert!(a.contains("pub fn GetA"));
+    assert!(!b.contains("pub fn GetA"));
+
+    // the full definition replaces the opaque struct
+    assert!(!a.contains("pub struct struct_Opaque"));
+    assert!(b.contains("pub struct struct_Opaque {"));
+    assert!(a.contains("use crate::root::b::struct_Opaque;"));
+
+    // conflicting declarations are reported
+    assert!(a.contains("pub type CONFLICT = i32;"));
+    assert!(!b.contains("pub type CONFLICT"));
+    assert_eq!(
+        bindings.diagnostics(),
+        &[
+            "\"CONFLICT\" is declared differently in root/a.h and root/b.h, keeping the former"
+                .to_string()
+        ]
+    );
+}
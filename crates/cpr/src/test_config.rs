@@ -0,0 +1,140 @@
+use crate::{
+    config::{Error, Header, Project},
+    frontend::features::{CompilerProfile, FeatureTest},
+    translator::{Arch, CTypes, DataModel},
+};
+use indoc::indoc;
+use std::path::{Path, PathBuf};
+
+#[test]
+fn config_file() {
+    let mut project: Project = indoc!(
+        r#"
+        [[target]]
+        name = "projectedfslib"
+        headers = ["samples/projectedfslib/root.h"]
+        output = "out/projectedfslib"
+        arches = ["x86", "x86-64"]
+        defines = ["WIN32_LEAN_AND_MEAN", "NTDDI_VERSION=0x0A000006"]
+
+        [target.allow]
+        functions = ["Prj.*"]
+
+        [target.block]
+        headers = ["um/winnt\\.h"]
+
+        [[target.include-dirs]]
+        pkg = "um"
+        path = "/sdk/um"
+
+        [[target]]
+        name = "wintrust"
+        headers = [
+            "<windows.h>",
+            { path = "include/wintrust.h", pkg = "wintrust" },
+        ]
+        kits-path = "kits"
+        data-model = "lp64"
+        ctypes = "core-ffi"
+
+        [target.compiler-profile]
+        __has_attribute = { packed = 1 }
+
+        [target.types]
+        GUID = "::winapi::shared::guiddef::GUID"
+        "#
+    )
+    .parse()
+    .unwrap();
+    project.resolve_paths(Path::new("/project"));
+
+    let t = &project.targets[0];
+    assert_eq!(t.name, "projectedfslib");
+    assert_eq!(
+        t.headers,
+        vec![Header::Path(
+            "/project/samples/projectedfslib/root.h".into()
+        )]
+    );
+    assert_eq!(t.include_dirs[0].pkg, "um");
+    assert_eq!(t.include_dirs[0].path, PathBuf::from("/sdk/um"));
+    assert_eq!(t.allow.functions, vec!["Prj.*".to_string()]);
+    assert_eq!(t.block.headers, vec![r"um/winnt\.h".to_string()]);
+    assert_eq!(t.arches(), vec![Arch::X86, Arch::X86_64]);
+    assert_eq!(
+        t.output_dir(Arch::X86),
+        Some(PathBuf::from("/project/out/projectedfslib/x86"))
+    );
+    assert!(t.builder(Arch::X86).is_ok());
+    assert_eq!(t.data_model, None);
+    assert_eq!(t.ctypes, CTypes::Fixed);
+
+    let t = &project.targets[1];
+    assert_eq!(
+        t.headers,
+        vec![
+            // looked up in include directories, so left alone
+            Header::Path("<windows.h>".into()),
+            Header::Package {
+                path: "/project/include/wintrust.h".into(),
+                pkg: "wintrust".into(),
+            },
+        ]
+    );
+    assert_eq!(t.headers[0].stem().as_deref(), Some("windows"));
+    assert_eq!(t.kits_path, Some(PathBuf::from("/project/kits")));
+    assert_eq!(t.arches(), vec![Arch::X86_64]);
+    assert_eq!(t.output_dir(Arch::X86_64), None);
+    assert_eq!(t.data_model, Some(DataModel::LP64));
+    assert_eq!(t.ctypes, CTypes::CoreFfi);
+    let mut profile = CompilerProfile::msvc();
+    profile.set(FeatureTest::Attribute, "__packed__", 1);
+    assert_eq!(t.compiler_profile, profile);
+    assert_eq!(
+        t.types.get("GUID").map(String::as_str),
+        Some("::winapi::shared::guiddef::GUID")
+    );
+}
+
+#[test]
+fn manifest_metadata() {
+    let project = Project::from_manifest_str(indoc!(
+        r#"
+        [package]
+        name = "projectedfslib-sys"
+
+        [[package.metadata.cpr.target]]
+        name = "projectedfslib"
+        headers = ["root.h"]
+        crate-name = "projectedfslib-sys"
+        "#
+    ))
+    .unwrap()
+    .unwrap();
+    assert_eq!(
+        project.targets[0].crate_name.as_deref(),
+        Some("projectedfslib-sys")
+    );
+
+    let project = Project::from_manifest_str("[package]\nname = \"foo\"\n").unwrap();
+    assert!(project.is_none());
+}
+
+#[test]
+fn invalid_config() {
+    let res: Result<Project, _> = "[[target]]\nname = \"foo\"\nheader = \"foo.h\"\n".parse();
+    assert!(matches!(res, Err(Error::Toml(_))));
+
+    let res: Result<Project, _> = "[[target]]\nname = \"foo\"\narches = [\"arm\"]\n".parse();
+    assert!(matches!(res, Err(Error::Toml(_))));
+
+    let res: Result<Project, _> =
+        "[[target]]\nname = \"foo\"\n[target.compiler-profile]\n__has_magic = {}\n".parse();
+    assert!(matches!(res, Err(Error::Toml(_))));
+
+    let project: Project = "[[target]]\nname = \"foo\"\n".parse().unwrap();
+    assert!(matches!(
+        project.targets[0].builder(Arch::X86_64),
+        Err(Error::NoHeader(_))
+    ));
+}
@@ -0,0 +1,173 @@
+//! Packs bitfields into storage units, the way MSVC lays them out.
+
+use super::{rg, DataModel};
+use std::collections::HashMap;
+
+/// An integer type a bitfield can be declared with
+#[derive(Debug, Clone, Copy)]
+struct Integer {
+    bits: u32,
+    kind: rg::BitfieldKind,
+}
+
+/// Replaces the bitfields of every struct and union in `units` with storage
+/// fields and accessors. Typedefs are looked up across all `units`.
+///
+/// MSVC allocates a storage unit of the bitfield's declared type, and packs
+/// the following bitfields into it as long as they have a type of the same
+/// size and fit in the bits that are left. Anything else, including a
+/// zero-width bitfield, starts a new unit.
+pub fn allocate(units: &mut [rg::Unit], data_model: DataModel) {
+    let mut aliases = HashMap::new();
+    let mut enums = Vec::new();
+    for unit in units.iter() {
+        for tl in &unit.toplevels {
+            match tl {
+                rg::TopLevel::AliasDeclaration(ad) => {
+                    aliases.insert(ad.name.value.clone(), ad.typ.clone());
+                }
+                rg::TopLevel::EnumDeclaration(ed) => enums.push(ed.name.value.clone()),
+                _ => {}
+            }
+        }
+    }
+    let resolve = |typ: &rg::Type| {
+        let mut typ = typ;
+        // typedefs can't be recursive, but stay safe
+        for _ in 0..64 {
+            let name = match typ {
+                rg::Type::Name(name) => &name.value,
+                _ => return None,
+            };
+            if let Some(integer) = integer(name, data_model) {
+                return Some(integer);
+            }
+            if enums.contains(name) {
+                return Some(Integer {
+                    bits: 32,
+                    kind: rg::BitfieldKind::Enum,
+                });
+            }
+            typ = aliases.get(name)?;
+        }
+        None
+    };
+
+    for unit in units.iter_mut() {
+        for tl in &mut unit.toplevels {
+            match tl {
+                rg::TopLevel::StructDeclaration(d) => {
+                    d.bitfields = allocate_fields(&d.name, &mut d.fields, false, &resolve);
+                }
+                rg::TopLevel::UnionDeclaration(d) => {
+                    d.bitfields = allocate_fields(&d.name, &mut d.fields, true, &resolve);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn allocate_fields(
+    name: &rg::Identifier,
+    fields: &mut Vec<rg::StructField>,
+    union: bool,
+    resolve: &dyn Fn(&rg::Type) -> Option<Integer>,
+) -> Vec<rg::Bitfield> {
+    if fields.iter().all(|field| field.bits.is_none()) {
+        return Vec::new();
+    }
+
+    let mut bitfields = Vec::new();
+    let mut allocated = Vec::new();
+    let mut storage_count = 0;
+    // size and used bits of the storage unit being filled
+    let mut current: Option<(u32, u32)> = None;
+
+    for mut field in fields.drain(..) {
+        let width = match field.bits {
+            Some(width) => width,
+            None => {
+                current = None;
+                allocated.push(field);
+                continue;
+            }
+        };
+        if width == 0 {
+            current = None;
+            continue;
+        }
+        let integer = match resolve(&field.typ) {
+            Some(integer) if width <= integer.bits => integer,
+            _ => {
+                log::warn!(
+                    "{}: can't lay out bitfield {:?} of type {}, keeping it as a whole field",
+                    name.value,
+                    field.name.value,
+                    field.typ
+                );
+                current = None;
+                if !field.name.value.is_empty() {
+                    field.bits = None;
+                    allocated.push(field);
+                }
+                continue;
+            }
+        };
+
+        let offset = match current {
+            Some((bits, used)) if !union && bits == integer.bits && used + width <= bits => used,
+            _ => {
+                storage_count += 1;
+                allocated.push(rg::StructField {
+                    name: storage_name(storage_count),
+                    typ: rg::Type::Name(rg::Identifier::name(&format!("u{}", integer.bits))),
+                    bits: None,
+                });
+                0
+            }
+        };
+        current = Some((integer.bits, offset + width));
+
+        if !field.name.value.is_empty() {
+            bitfields.push(rg::Bitfield {
+                name: field.name,
+                typ: field.typ,
+                kind: integer.kind,
+                storage: storage_name(storage_count),
+                storage_bits: integer.bits,
+                offset,
+                width,
+            });
+        }
+    }
+
+    *fields = allocated;
+    bitfields
+}
+
+fn storage_name(index: usize) -> rg::Identifier {
+    rg::Identifier::name(&format!("_bitfield_{}", index))
+}
+
+/// Size and signedness of the types `visit_type` translates C integer types
+/// to, with the sizes of `data_model`.
+fn integer(name: &str, data_model: DataModel) -> Option<Integer> {
+    use rg::BitfieldKind as K;
+    let name = name.rsplit("::").next().unwrap_or(name);
+    let (bits, kind) = match name {
+        "bool" => (8, K::Bool),
+        "c_char" | "c_schar" | "i8" => (8, K::Integer { signed: true }),
+        "c_uchar" | "u8" => (8, K::Integer { signed: false }),
+        "c_short" | "i16" => (16, K::Integer { signed: true }),
+        "c_ushort" | "u16" => (16, K::Integer { signed: false }),
+        "c_long" => (data_model.long_bits(), K::Integer { signed: true }),
+        "c_ulong" => (data_model.long_bits(), K::Integer { signed: false }),
+        "c_int" | "i32" => (32, K::Integer { signed: true }),
+        "c_uint" | "u32" => (32, K::Integer { signed: false }),
+        "c_longlong" | "i64" => (64, K::Integer { signed: true }),
+        "c_ulonglong" | "u64" => (64, K::Integer { signed: false }),
+        _ => return None,
+    };
+    Some(Integer { bits, kind })
+}
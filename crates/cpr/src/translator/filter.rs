@@ -0,0 +1,159 @@
+//! Allow and block lists for generated items.
+//!
+//! When anything is allowlisted, only allowlisted items are emitted, along
+//! with every type they transitively refer to. Blocked items are never
+//! emitted, even when referred to.
+
+use super::rg;
+use crate::frontend::SourceProvider;
+use regex::RegexSet;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+/// Regular expressions, matched against whole names. Struct and enum tags
+/// are matched with their prefix, ie. `struct_FOO`. Headers are matched
+/// against paths like `um/WinTrust.h`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ItemPatterns {
+    #[serde(default)]
+    pub functions: Vec<String>,
+    #[serde(default)]
+    pub types: Vec<String>,
+    #[serde(default)]
+    pub constants: Vec<String>,
+    #[serde(default)]
+    pub headers: Vec<String>,
+}
+
+impl ItemPatterns {
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+            && self.types.is_empty()
+            && self.constants.is_empty()
+            && self.headers.is_empty()
+    }
+}
+
+#[derive(Debug)]
+struct ItemSets {
+    empty: bool,
+    functions: RegexSet,
+    types: RegexSet,
+    constants: RegexSet,
+    headers: RegexSet,
+}
+
+impl ItemSets {
+    fn new(patterns: &ItemPatterns) -> Result<Self, regex::Error> {
+        let set =
+            |patterns: &[String]| RegexSet::new(patterns.iter().map(|p| format!("^(?:{})$", p)));
+        Ok(Self {
+            empty: patterns.is_empty(),
+            functions: set(&patterns.functions)?,
+            types: set(&patterns.types)?,
+            constants: set(&patterns.constants)?,
+            headers: set(&patterns.headers)?,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.empty
+    }
+
+    fn matches(&self, tl: &rg::TopLevel, header: &str) -> bool {
+        let names = match tl {
+            rg::TopLevel::FunctionDeclaration(_) => &self.functions,
+            // like bindgen's variables, constants and statics go together
+            rg::TopLevel::Constant(_) | rg::TopLevel::StaticDeclaration(_) => &self.constants,
+            rg::TopLevel::AliasDeclaration(_)
+            | rg::TopLevel::StructDeclaration(_)
+            | rg::TopLevel::UnionDeclaration(_)
+            | rg::TopLevel::EnumDeclaration(_) => &self.types,
+        };
+        names.is_match(tl.name()) || self.headers.is_match(header)
+    }
+}
+
+#[derive(Debug)]
+pub struct ItemFilter {
+    allow: ItemSets,
+    block: ItemSets,
+}
+
+impl ItemFilter {
+    pub fn new(allow: &ItemPatterns, block: &ItemPatterns) -> Result<Self, regex::Error> {
+        Ok(Self {
+            allow: ItemSets::new(allow)?,
+            block: ItemSets::new(block)?,
+        })
+    }
+
+    /// Removes toplevels that aren't allowed from `units`.
+    pub fn apply(&self, provider: &dyn SourceProvider, units: &mut [rg::Unit]) {
+        if self.allow.is_empty() && self.block.is_empty() {
+            return;
+        }
+
+        let headers: Vec<String> = units
+            .iter()
+            .map(|unit| {
+                provider
+                    .info(unit.id)
+                    .map(|info| info.path.to_string())
+                    .unwrap_or_default()
+            })
+            .collect();
+        let keep = self.closure(units, &headers);
+
+        for (ui, unit) in units.iter_mut().enumerate() {
+            let before = unit.toplevels.len();
+            let mut ti = 0;
+            unit.toplevels.retain(|_| {
+                ti += 1;
+                keep.contains(&(ui, ti - 1))
+            });
+            log::debug!(
+                "{} | kept {} of {} items",
+                headers[ui],
+                unit.toplevels.len(),
+                before
+            );
+        }
+    }
+
+    /// Returns (unit index, toplevel index) of every item to keep
+    fn closure(&self, units: &[rg::Unit], headers: &[String]) -> HashSet<(usize, usize)> {
+        let blocked =
+            |(ui, ti): (usize, usize)| self.block.matches(&units[ui].toplevels[ti], &headers[ui]);
+
+        let mut index: HashMap<&str, Vec<(usize, usize)>> = HashMap::new();
+        let mut queue = Vec::new();
+        for (ui, unit) in units.iter().enumerate() {
+            for (ti, tl) in unit.toplevels.iter().enumerate() {
+                index.entry(tl.name()).or_default().push((ui, ti));
+                if blocked((ui, ti)) {
+                    continue;
+                }
+                if self.allow.is_empty() || self.allow.matches(tl, &headers[ui]) {
+                    queue.push((ui, ti));
+                }
+            }
+        }
+
+        let mut keep = HashSet::new();
+        while let Some(key) = queue.pop() {
+            if !keep.insert(key) {
+                continue;
+            }
+            units[key.0].toplevels[key.1].visit_type_names(&mut |name| {
+                for &dep in index.get(name).into_iter().flatten() {
+                    if !keep.contains(&dep) && !blocked(dep) {
+                        queue.push(dep);
+                    }
+                }
+            });
+        }
+        keep
+    }
+}
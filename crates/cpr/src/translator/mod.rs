@@ -1,13 +1,16 @@
 use indexmap::IndexSet;
 use lang_c::{ast, span::Node};
 use std::{
-    collections::hash_map::DefaultHasher,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
     hash::{Hash, Hasher},
 };
 
-mod rg;
+pub mod bitfields;
+pub mod filter;
+pub mod rg;
+pub mod symbols;
 mod utils;
-use crate::frontend::{FileId, SourceProvider, UnitDeclaration};
+use crate::frontend::{FileId, SourceProvider, Unit, UnitDeclaration};
 use utils::*;
 
 struct Translator<'a> {
@@ -17,27 +20,67 @@ struct Translator<'a> {
     forward_struct_names: IndexSet<String>,
     declared_struct_names: IndexSet<String>,
     declared_alias_names: IndexSet<String>,
+    libraries: Vec<String>,
 }
 
 pub struct Config {
     pub arch: Arch,
+    /// Sizes of the C types, ie. `DataModel::LLP64` for 64-bit Windows
+    pub data_model: DataModel,
+    /// How to spell the C types in Rust
+    pub ctypes: CTypes,
+    /// Rust types to use instead of translating C types, by name, see
+    /// `symbols::override_types`
+    pub type_overrides: HashMap<String, String>,
 }
 
-#[derive(Clone, Copy)]
+impl Config {
+    /// Configuration for `arch`, with its Windows data model
+    pub fn new(arch: Arch) -> Self {
+        Self {
+            arch,
+            data_model: arch.data_model(),
+            ctypes: Default::default(),
+            type_overrides: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
 enum StructVisitMode {
     Forward,
     Collect,
 }
 
 /// Target architectures, named after LLVM
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
 pub enum Arch {
     /// 32-bit X86: Pentium-Pro and above
+    #[serde(rename = "x86")]
     X86,
     /// 64-bit X86: EMT64 and AMD64
+    #[serde(rename = "x86-64")]
     X86_64,
 }
 
+impl Arch {
+    /// Name as accepted by `--arch`, ie. `x86-64`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::X86 => "x86",
+            Self::X86_64 => "x86-64",
+        }
+    }
+
+    /// Data model of Windows on this architecture
+    pub fn data_model(self) -> DataModel {
+        match self {
+            Self::X86 => DataModel::ILP32,
+            Self::X86_64 => DataModel::LLP64,
+        }
+    }
+}
+
 impl Default for Arch {
     fn default() -> Self {
         Self::X86_64
@@ -54,6 +97,132 @@ impl argh::FromArgValue for Arch {
     }
 }
 
+/// Sizes of the C types on a target, named after the types that are 64-bit
+/// (or 32-bit for ILP32), see
+/// <https://en.cppreference.com/w/c/language/arithmetic_types#Data_models>
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+pub enum DataModel {
+    /// 32-bit Windows: `int`, `long` and pointers are 32-bit
+    #[serde(rename = "ilp32")]
+    ILP32,
+    /// 64-bit Windows: `long` is 32-bit, `long long` and pointers are 64-bit
+    #[serde(rename = "llp64")]
+    LLP64,
+    /// 64-bit Unix: `long` and pointers are 64-bit
+    #[serde(rename = "lp64")]
+    LP64,
+}
+
+impl DataModel {
+    /// Width of `long`
+    pub fn long_bits(self) -> u32 {
+        match self {
+            Self::ILP32 | Self::LLP64 => 32,
+            Self::LP64 => 64,
+        }
+    }
+
+    /// Size in bytes of a translated type, if it's made of primitive types,
+    /// ie. `4` for `c_long` on LLP64. Typedef names have no known size.
+    pub fn size_of(self, typ: &rg::Type) -> Option<u64> {
+        match typ {
+            rg::Type::Name(name) => {
+                let name = name.value.as_str();
+                let ctype = name
+                    .strip_prefix("::std::os::raw::c_")
+                    .or_else(|| name.strip_prefix("::core::ffi::c_"));
+                let name = match ctype {
+                    Some(ctype) if CTYPES.contains(&ctype) => self.fixed(ctype),
+                    Some(_) => return None,
+                    None => name,
+                };
+                match name {
+                    "i8" | "u8" | "bool" => Some(1),
+                    "i16" | "u16" => Some(2),
+                    "i32" | "u32" | "f32" => Some(4),
+                    "i64" | "u64" | "f64" => Some(8),
+                    "u128" => Some(16),
+                    _ => None,
+                }
+            }
+            rg::Type::Pointer { .. } => match self {
+                Self::ILP32 => Some(4),
+                Self::LLP64 | Self::LP64 => Some(8),
+            },
+            rg::Type::Array { inner, len } => {
+                let len = len.evaluate_with(&|typ| self.size_of(typ))?;
+                self.size_of(inner)?.checked_mul(len)
+            }
+            rg::Type::Function(_) => None,
+        }
+    }
+
+    /// Fixed-width type with the size and signedness of a C type, named as
+    /// in `c_long`
+    fn fixed(self, ctype: &str) -> &'static str {
+        match (ctype, self.long_bits()) {
+            // `char` is signed with MSVC, and on x86 Unix
+            ("char", _) | ("schar", _) => "i8",
+            ("uchar", _) => "u8",
+            ("short", _) => "i16",
+            ("ushort", _) => "u16",
+            ("int", _) | ("long", 32) => "i32",
+            ("uint", _) | ("ulong", 32) => "u32",
+            ("long", _) | ("longlong", _) => "i64",
+            ("ulong", _) | ("ulonglong", _) => "u64",
+            _ => unreachable!("not a C integer type: {:?}", ctype),
+        }
+    }
+
+    /// `wchar_t` is a UTF-16 code unit on Windows, and a 32-bit `int` on Unix
+    fn wchar_t(self) -> &'static str {
+        match self {
+            Self::ILP32 | Self::LLP64 => "u16",
+            Self::LP64 => "i32",
+        }
+    }
+
+    /// MSVC's `long double` is a `double`. On x86-64 Unix it's an 80-bit
+    /// x87 float, padded to 16 bytes, which Rust can only hold as storage.
+    fn long_double(self) -> &'static str {
+        match self {
+            Self::ILP32 | Self::LLP64 => "f64",
+            Self::LP64 => "u128",
+        }
+    }
+}
+
+/// C integer types, named as in `c_long`
+const CTYPES: &[&str] = &[
+    "char",
+    "schar",
+    "uchar",
+    "short",
+    "ushort",
+    "int",
+    "uint",
+    "long",
+    "ulong",
+    "longlong",
+    "ulonglong",
+];
+
+/// How to spell the C types in Rust
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CTypes {
+    /// Fixed-width types, sized for the data model, ie. `i32` for `long`
+    /// on LLP64. The bindings are the same whichever target builds them.
+    #[default]
+    Fixed,
+    /// `::std::os::raw::c_long` and friends, sized for the target that
+    /// builds the bindings, which must then match the data model
+    StdOsRaw,
+    /// `::core::ffi::c_long` and friends, like `StdOsRaw` but usable in
+    /// `no_std` crates
+    CoreFfi,
+}
+
 impl<'a> Translator<'a> {
     fn new(config: &'a Config, provider: &'a dyn SourceProvider, id: FileId) -> Self {
         Self {
@@ -63,6 +232,7 @@ impl<'a> Translator<'a> {
             declared_struct_names: Default::default(),
             forward_struct_names: Default::default(),
             declared_alias_names: Default::default(),
+            libraries: Default::default(),
         }
     }
 
@@ -195,33 +365,82 @@ impl<'a> Translator<'a> {
         let stack = &[name.as_ref()];
 
         let mut res = rg::StructDeclaration {
-            name: rg::Identifier::struct_name(&name),
+            name: struct_identifier(struty, &name),
             fields: Default::default(),
+            bitfields: Default::default(),
         };
 
         if let Some(declarations) = &struty.declarations {
+            let mut taken = field_names(declarations);
+
             for dtion in nodes(&declarations[..]) {
                 if let ast::StructDeclaration::Field(Node { node: field, .. }) = dtion {
                     let specifiers = &field.specifiers[..];
 
-                    for dtor in nodes(&field.declarators[..]) {
-                        if let Some(Node { node: dtor, .. }) = dtor.declarator.as_ref() {
-                            let sftup = StructFieldTuple { field, dtor };
-                            log::debug!("{:?} {:?}", specifiers, dtor);
-
-                            let id = match dtor.get_identifier() {
-                                Some(x) => x,
-                                None => panic!("anonymous struct fields aren't supported"),
+                    // C11 anonymous members, ie. `struct { union { ... }; }`,
+                    // get a name like the `DUMMYUNIONNAME` macros give them.
+                    if field.declarators.is_empty() {
+                        if let Some(kind) = anonymous_member_kind(field) {
+                            let prefix = match kind {
+                                ast::StructKind::Struct => "s",
+                                ast::StructKind::Union => "u",
                             };
+                            let name = (1..)
+                                .map(|n| match n {
+                                    1 => prefix.to_string(),
+                                    n => format!("{}{}", prefix, n),
+                                })
+                                .find(|name| !taken.contains(name))
+                                .unwrap();
+                            taken.insert(name.clone());
 
+                            let sftup = StructFieldTuple { field, dtor: None };
                             let typ = self.visit_type(stack, &sftup);
-                            let field = rg::StructField {
-                                name: rg::Identifier::name(&id.name),
+                            res.fields.push(rg::StructField {
+                                name: rg::Identifier::name(&name),
                                 typ,
-                            };
-                            res.fields.push(field);
+                                bits: None,
+                            });
                         }
                     }
+
+                    for sdtor in nodes(&field.declarators[..]) {
+                        let dtor = sdtor.declarator.as_ref().map(borrow_node);
+                        let bits = sdtor.bit_width.as_ref().and_then(|width| {
+                            let data_model = self.config.data_model;
+                            let width = width.node.as_expr(self);
+                            let bits = width.evaluate_with(&|typ| data_model.size_of(typ));
+                            if bits.is_none() {
+                                log::warn!(
+                                    "{}: can't evaluate the width of bitfield {:?}, keeping it as a whole field",
+                                    name,
+                                    dtor.and_then(|dtor| dtor.find_identifier())
+                                        .map_or("", |id| id.name.as_str())
+                                );
+                            }
+                            bits.map(|bits| bits as u32)
+                        });
+                        let sftup = StructFieldTuple { field, dtor };
+                        log::debug!("{:?} {:?}", specifiers, dtor);
+
+                        let name = match dtor {
+                            Some(dtor) => match dtor.find_identifier() {
+                                Some(x) => x.name.as_str(),
+                                None => continue,
+                            },
+                            // unnamed bitfield, ie. `int : 3`, used for padding
+                            None if bits.is_some() => "",
+                            None => continue,
+                        };
+
+                        let typ = self.visit_type(stack, &sftup);
+                        let field = rg::StructField {
+                            name: rg::Identifier::name(name),
+                            typ,
+                            bits,
+                        };
+                        res.fields.push(field);
+                    }
                 }
             }
         }
@@ -231,19 +450,19 @@ impl<'a> Translator<'a> {
             return name;
         }
 
-        match mode {
-            StructVisitMode::Forward => {
-                if res.fields.is_empty() {
-                    self.forward_struct_names.insert(res.name.value);
-                } else {
-                    self.declared_struct_names.insert(res.name.value.clone());
-                    self.push(res);
-                }
-            }
-            StructVisitMode::Collect => {
-                self.declared_struct_names.insert(res.name.value.clone());
-                self.push(res);
-            }
+        if mode == StructVisitMode::Forward && res.fields.is_empty() {
+            self.forward_struct_names.insert(res.name.value);
+            return name;
+        }
+
+        self.declared_struct_names.insert(res.name.value.clone());
+        match struty.kind.node {
+            ast::StructKind::Struct => self.push(res),
+            ast::StructKind::Union => self.push(rg::UnionDeclaration {
+                name: res.name,
+                fields: res.fields,
+                bitfields: res.bitfields,
+            }),
         }
         name
     }
@@ -274,6 +493,12 @@ impl<'a> Translator<'a> {
 
     #[must_use]
     fn visit_type(&self, stack: &[&str], typ: &dyn Typed) -> rg::Type {
+        self.visit_qualified_type(stack, typ).0
+    }
+
+    /// Translates a type, and tells whether it's `const`-qualified
+    #[must_use]
+    fn visit_qualified_type(&self, stack: &[&str], typ: &dyn Typed) -> (rg::Type, bool) {
         let mut signed = None;
         let mut longness = 0;
         let original_specs: Vec<_> = typ.typespecs().collect();
@@ -326,29 +551,33 @@ impl<'a> Translator<'a> {
             original_specs,
         );
 
-        let mut res = match &specs[0] {
+        let res = match &specs[0] {
             TS::Int => match longness {
-                -1 => pick_sign(signed, ctype("ushort"), ctype("short")),
-                0 => pick_sign(signed, ctype("uint"), ctype("int")),
-                1 => pick_sign(signed, ctype("ulong"), ctype("long")),
-                _ => pick_sign(signed, ctype("ulonglong"), ctype("longlong")),
+                -1 => pick_sign(signed, self.ctype("ushort"), self.ctype("short")),
+                0 => pick_sign(signed, self.ctype("uint"), self.ctype("int")),
+                1 => pick_sign(signed, self.ctype("ulong"), self.ctype("long")),
+                _ => pick_sign(signed, self.ctype("ulonglong"), self.ctype("longlong")),
             },
             TS::Char => match signed {
-                Some(true) => ctype("schar"),
-                Some(false) => ctype("uchar"),
-                None => ctype("char"),
+                Some(true) => self.ctype("schar"),
+                Some(false) => self.ctype("uchar"),
+                None => self.ctype("char"),
             },
             TS::Float => builtin("f32"),
             TS::Double => match longness {
-                1 => builtin("[u8; 12]"),
+                1 => builtin(self.config.data_model.long_double()),
                 _ => builtin("f64"),
             },
             TS::Bool => builtin("bool"),
-            TS::Void => builtin("::core::ffi::c_void"),
+            TS::Void => builtin(VOID),
             TS::Int8 => pick_sign(signed, builtin("u8"), builtin("i8")),
             TS::Int16 => pick_sign(signed, builtin("u16"), builtin("i16")),
             TS::Int32 => pick_sign(signed, builtin("u32"), builtin("i32")),
             TS::Int64 => pick_sign(signed, builtin("u64"), builtin("i64")),
+            // a typedef in MSVC's headers, but a builtin type in C++
+            TS::TypedefName(Node { node: id, .. }) if id.name == "wchar_t" => {
+                builtin(self.config.data_model.wchar_t())
+            }
             TS::TypedefName(Node { node: id, .. }) => builtin(&id.name),
             TS::Struct(Node { node: struty, .. }) => {
                 let id = &struty
@@ -357,7 +586,7 @@ impl<'a> Translator<'a> {
                     .map(|x| x.node.name.clone())
                     .unwrap_or_else(|| self.hash_name(stack, struty));
 
-                rg::Type::Name(rg::Identifier::struct_name(id))
+                rg::Type::Name(struct_identifier(struty, id))
             }
             TS::Enum(Node { node: enumty, .. }) => {
                 let id = &enumty
@@ -375,14 +604,131 @@ impl<'a> Translator<'a> {
             ),
         };
 
-        for _d in 0..typ.pointer_depth() {
+        let konst = typ.is_const();
+        match typ.declarator() {
+            Some(dtor) => self.visit_derived(
+                stack,
+                typ,
+                (res, konst),
+                &dtor.derived[..],
+                dtor.get_nested(),
+            ),
+            None => (res, konst),
+        }
+    }
+
+    /// Wraps `res` in the pointers, arrays and functions of a declarator.
+    /// Pointers apply first, then arrays and functions from right to left,
+    /// then the nested declarator, so `T *a[2][3]` is an array of 2 arrays of
+    /// 3 pointers to `T`, `T (*a)[3]` is a pointer to an array of 3 `T`, and
+    /// `T (*f)(int)` is a pointer to a function returning `T`.
+    ///
+    /// Along with the type, tracks whether it's `const`-qualified, which
+    /// makes the pointers to it `*const`: `const char **` is a `*mut *const
+    /// c_char` and `char *const *` is a `*const *mut c_char`.
+    #[must_use]
+    fn visit_derived(
+        &self,
+        stack: &[&str],
+        typ: &dyn Typed,
+        (mut res, mut konst): (rg::Type, bool),
+        derived: &[Node<ast::DerivedDeclarator>],
+        nested: Option<&ast::Declarator>,
+    ) -> (rg::Type, bool) {
+        use ast::DerivedDeclarator as DD;
+
+        let (pointers, suffixes): (Vec<_>, Vec<_>) =
+            nodes(derived).partition(|d| matches!(d, DD::Pointer(_)));
+        for pointer in pointers {
             res = rg::Type::Pointer {
-                konst: typ.is_const(),
+                konst,
                 inner: Box::new(res),
+            };
+            konst = match pointer {
+                DD::Pointer(quals) => nodes(&quals[..])
+                    .filter_map(|q| q.as_specqual())
+                    .any(|sq| sq.is_const()),
+                _ => unreachable!(),
+            };
+        }
+        for suffix in suffixes.into_iter().rev() {
+            // an array of const elements is const itself, a function isn't
+            if !matches!(suffix, DD::Array(_)) {
+                konst = false;
+            }
+            res = match suffix {
+                DD::Array(arr) => rg::Type::Array {
+                    inner: Box::new(res),
+                    len: Box::new(self.visit_array_size(&arr.node.size)),
+                },
+                DD::Function(fdecl) => {
+                    rg::Type::Function(self.visit_function_type(stack, typ, res, &fdecl.node))
+                }
+                // `int (*f)()` takes unspecified arguments, call it with none
+                DD::KRFunction(_) => rg::Type::Function(rg::FunctionType {
+                    params: Default::default(),
+                    variadic: false,
+                    ret: return_type(res),
+                    abi: self.visit_abi(typ),
+                }),
+                DD::Pointer(_) => unreachable!(),
             }
         }
 
-        res
+        match nested {
+            Some(d) => self.visit_derived(stack, typ, (res, konst), &d.derived[..], d.get_nested()),
+            None => (res, konst),
+        }
+    }
+
+    #[must_use]
+    fn visit_function_type(
+        &self,
+        stack: &[&str],
+        typ: &dyn Typed,
+        ret: rg::Type,
+        fdecl: &ast::FunctionDeclarator,
+    ) -> rg::FunctionType {
+        let params = if fdecl.takes_nothing() {
+            // function is `fun(void)`, ignore the void
+            Default::default()
+        } else {
+            nodes(&fdecl.parameters[..])
+                .map(|param| self.visit_param_type(stack, param))
+                .collect()
+        };
+        rg::FunctionType {
+            params,
+            variadic: fdecl.is_variadic(),
+            ret: return_type(ret),
+            abi: self.visit_abi(typ),
+        }
+    }
+
+    /// Returns the length of an array, evaluated if it only involves
+    /// integers, ie. `MAX_PATH + 1` once expanded.
+    fn visit_array_size(&self, size: &ast::ArraySize) -> rg::Expr {
+        match size {
+            ast::ArraySize::VariableExpression(expr) | ast::ArraySize::StaticExpression(expr) => {
+                let expr = expr.node.as_expr(self);
+                match expr.evaluate() {
+                    Some(n) => rg::Expr::Integer(n),
+                    None => expr,
+                }
+            }
+            // flexible array member, ie. `WCHAR Name[]`
+            ast::ArraySize::Unknown | ast::ArraySize::VariableUnknown => rg::Expr::Integer(0),
+        }
+    }
+
+    /// Arrays decay to pointers when passed as parameters, so `int v[4]` is
+    /// translated as `int *v`.
+    #[must_use]
+    fn visit_param_type(&self, stack: &[&str], param: &ast::ParameterDeclaration) -> rg::Type {
+        match self.visit_qualified_type(stack, param) {
+            (rg::Type::Array { inner, .. }, konst) => rg::Type::Pointer { konst, inner },
+            (typ, _) => typ,
+        }
     }
 
     fn visit_declarator(
@@ -394,45 +740,8 @@ impl<'a> Translator<'a> {
         // println!("declaration = {:#?}", dtion);
         // println!("declarator  = {:#?}", dtor);
 
-        let id = match dtor.get_identifier() {
+        let id = match dtor.find_identifier() {
             None => {
-                if let ast::DeclaratorKind::Declarator(nested) = &dtor.kind.node {
-                    let nested = borrow_node(nested.as_ref());
-                    if let Some(ast::StorageClassSpecifier::Typedef) = dtion.get_storage_class() {
-                        if let Some(id) = nested.get_identifier() {
-                            if let Some(fdecl) = dtor.get_function() {
-                                let mut ft = rg::FunctionType { params: vec![] };
-                                for param in nodes(&fdecl.parameters[..]) {
-                                    ft.params.push(self.visit_type(stack, param));
-                                }
-                                let ad = rg::AliasDeclaration {
-                                    name: rg::Identifier::name(&id.name),
-                                    typ: rg::Type::Function(ft),
-                                };
-                                self.push(ad);
-                                return;
-                            } else {
-                                if nested.pointer_depth() == 1 {
-                                    // courtesy of this weird thing in <winnt.h>:
-                                    //
-                                    //     typedef int (A)(int);
-                                    //     typedef A (*B);
-                                    //     typedef B C;
-                                    let ad = rg::AliasDeclaration {
-                                        name: rg::Identifier::name(&id.name),
-                                        typ: self.visit_type(stack, &DeclTuple { dtion, dtor }),
-                                    };
-                                    self.push(ad);
-                                    return;
-                                }
-                                log::debug!("no fdecl, nested = {:#?}", nested);
-                            }
-                        } else {
-                            log::debug!("no id");
-                        }
-                    }
-                }
-
                 log::debug!(
                     "visit_declarator: dtor without identifier {:#?} {:#?}",
                     dtion,
@@ -452,9 +761,14 @@ impl<'a> Translator<'a> {
                 typ,
             };
             self.push(ad);
-        } else if let Some(fdecl) = dtor.get_function() {
+        } else if let (Some(fdecl), Some(_)) = (dtor.get_function(), dtor.get_identifier()) {
             let fd = self.visit_fdecl(stack, dtion, id, dtor, fdecl);
             self.push(fd);
+        } else if dtion.get_storage_class() == Some(&ast::StorageClassSpecifier::Extern)
+            || dtion.is_dllimport()
+        {
+            let sd = self.visit_static(stack, dtion, id, dtor);
+            self.push(sd);
         } else {
             log::debug!(
                 "visit_declarator: unsure what to do with {:#?} {:#?}",
@@ -474,16 +788,18 @@ impl<'a> Translator<'a> {
         fdecl: &ast::FunctionDeclarator,
     ) -> rg::FunctionDeclaration {
         let ftup = DeclTuple { dtion, dtor };
+        let ft = match self.visit_type(stack, &ftup) {
+            rg::Type::Function(ft) => ft,
+            typ => unreachable!("function declarator has type {:?}", typ),
+        };
 
         let mut res = rg::FunctionDeclaration {
             name: rg::Identifier::name(&id.name),
+            links: self.libraries.clone(),
             params: Default::default(),
-            ret: if ftup.is_void() {
-                // function is `void fun()`, ignore the void
-                None
-            } else {
-                Some(self.visit_type(stack, &ftup))
-            },
+            variadic: ft.variadic,
+            abi: ft.abi,
+            ret: ft.ret.map(|ret| *ret),
         };
 
         if fdecl.takes_nothing() {
@@ -492,13 +808,13 @@ impl<'a> Translator<'a> {
             for (i, param) in nodes(&fdecl.parameters[..]).enumerate() {
                 let name = param
                     .declarator()
-                    .and_then(|dtor| dtor.get_identifier())
+                    .and_then(|dtor| dtor.find_identifier())
                     .map(|id| id.name.clone())
                     .unwrap_or_else(|| format!("__arg{}", i));
 
                 res.params.push(rg::FunctionParam {
                     name: rg::Identifier::name(&name),
-                    typ: self.visit_type(stack, param),
+                    typ: self.visit_param_type(stack, param),
                 });
             }
         }
@@ -506,6 +822,68 @@ impl<'a> Translator<'a> {
         res
     }
 
+    /// Translates a global variable defined elsewhere. It's only mutable
+    /// when the object itself isn't const, ie. `extern const char *name`
+    /// is a `static mut` but `extern const IID IID_IUnknown` isn't.
+    #[must_use]
+    fn visit_static(
+        &self,
+        stack: &[&str],
+        dtion: &ast::Declaration,
+        id: &ast::Identifier,
+        dtor: &ast::Declarator,
+    ) -> rg::StaticDeclaration {
+        let (typ, konst) = self.visit_qualified_type(stack, &DeclTuple { dtion, dtor });
+        let dllimport = dtion.is_dllimport();
+        if dllimport && self.libraries.is_empty() {
+            log::warn!(
+                "{} is dllimport but no library was named with #pragma comment(lib), \
+                 it will need a #[link] attribute to be found",
+                id.name
+            );
+        }
+
+        rg::StaticDeclaration {
+            name: rg::Identifier::name(&id.name),
+            links: self.libraries.clone(),
+            typ,
+            mutable: !konst,
+            dllimport,
+        }
+    }
+
+    /// Spells a C integer type, named as in `c_long`
+    fn ctype(&self, name: &str) -> rg::Type {
+        match self.config.ctypes {
+            CTypes::Fixed => builtin(self.config.data_model.fixed(name)),
+            CTypes::StdOsRaw => builtin(format!("::std::os::raw::c_{}", name)),
+            CTypes::CoreFfi => builtin(format!("::core::ffi::c_{}", name)),
+        }
+    }
+
+    /// Picks the ABI that matches a calling convention on the target. Only
+    /// 32-bit x86 has several of them, x86-64 ignores all the keywords but
+    /// `__vectorcall`.
+    fn visit_abi(&self, typ: &dyn Typed) -> rg::Abi {
+        use ast::CallingConvention as CC;
+        let x86 = self.config.arch == Arch::X86;
+        match typ.calling_convention() {
+            None | Some(CC::Cdecl) => rg::Abi::C,
+            Some(CC::Stdcall) => rg::Abi::System,
+            Some(CC::Fastcall) if x86 => rg::Abi::Fastcall,
+            Some(CC::Thiscall) if x86 => rg::Abi::Thiscall,
+            Some(CC::Fastcall) | Some(CC::Thiscall) => rg::Abi::C,
+            Some(CC::Vectorcall) => {
+                log::warn!("__vectorcall functions need the unstable abi_vectorcall feature");
+                rg::Abi::Vectorcall
+            }
+            Some(CC::Clrcall) => {
+                log::warn!("__clrcall is for managed code, translating it as __cdecl");
+                rg::Abi::C
+            }
+        }
+    }
+
     fn hash_name<T>(&self, stack: &[&str], t: &T) -> String
     where
         T: Hash,
@@ -524,6 +902,7 @@ impl<'a> Translator<'a> {
             .difference(&self.declared_struct_names)
             .map(|name| rg::StructDeclaration {
                 fields: Default::default(),
+                bitfields: Default::default(),
                 name: rg::Identifier::name(name),
             })
             .collect();
@@ -564,6 +943,37 @@ impl AsExpr for ast::CastExpression {
     }
 }
 
+impl AsExpr for ast::UnaryOperatorExpression {
+    fn as_expr(&self, trans: &Translator) -> rg::Expr {
+        use ast::UnaryOperator as UO;
+        match (&self.operator.node, &self.operand.node) {
+            // `sizeof(T)`, when `T` isn't known to be a typedef name yet
+            (UO::SizeOf, ast::Expression::Identifier(id)) => {
+                rg::Expr::SizeOf(builtin(&id.node.name))
+            }
+            (UO::Minus, operand)
+            | (UO::Plus, operand)
+            | (UO::Complement, operand)
+            | (UO::Negate, operand) => rg::Expr::UnaryOperator(
+                self.operator.node.clone(),
+                Box::new(operand.as_expr(trans)),
+            ),
+            (UO::SizeOf, _) => unsupported("`sizeof` of an expression"),
+            (UO::Address, _) => unsupported("`&` operator"),
+            (UO::Indirection, _) => unsupported("`*` operator"),
+            (UO::PostIncrement, _) | (UO::PreIncrement, _) => unsupported("`++` operator"),
+            (UO::PostDecrement, _) | (UO::PreDecrement, _) => unsupported("`--` operator"),
+        }
+    }
+}
+
+/// An expression with no Rust equivalent, so that it fails to compile
+/// instead of aborting the translation
+fn unsupported(what: &str) -> rg::Expr {
+    log::warn!("unsupported C expression: {}", what);
+    rg::Expr::Unsupported(what.to_string())
+}
+
 impl AsExpr for ast::Identifier {
     fn as_expr(&self, _trans: &Translator) -> rg::Expr {
         rg::Expr::Identifier(self.name.clone())
@@ -575,38 +985,40 @@ impl AsExpr for ast::Expression {
         match self {
             ast::Expression::Identifier(v) => v.node.as_expr(trans),
             ast::Expression::Constant(v) => v.node.as_expr(trans),
-            ast::Expression::StringLiteral(_) => todo!(),
-            ast::Expression::GenericSelection(_) => todo!(),
-            ast::Expression::Member(_) => todo!(),
-            ast::Expression::Call(_) => todo!(),
-            ast::Expression::CompoundLiteral(_) => todo!(),
+            ast::Expression::StringLiteral(_) => unsupported("string literal"),
+            ast::Expression::GenericSelection(_) => unsupported("`_Generic` selection"),
+            ast::Expression::Member(_) => unsupported("member access"),
+            ast::Expression::Call(_) => unsupported("function call"),
+            ast::Expression::CompoundLiteral(_) => unsupported("compound literal"),
             ast::Expression::SizeOf(ty) => rg::Expr::SizeOf(trans.visit_type(&[], &ty.node)),
             ast::Expression::AlignOf(ty) => rg::Expr::AlignOf(trans.visit_type(&[], &ty.node)),
-            ast::Expression::UnaryOperator(_) => todo!(),
+            ast::Expression::UnaryOperator(v) => v.node.as_expr(trans),
             ast::Expression::Cast(v) => v.node.as_expr(trans),
             ast::Expression::BinaryOperator(v) => v.node.as_expr(trans),
-            ast::Expression::Conditional(_) => todo!(),
-            ast::Expression::Comma(_) => todo!(),
-            ast::Expression::OffsetOf(_) => todo!(),
-            ast::Expression::VaArg(_) => todo!(),
-            ast::Expression::Statement(_) => todo!(),
+            ast::Expression::Conditional(_) => unsupported("conditional expression"),
+            ast::Expression::Comma(_) => unsupported("comma expression"),
+            ast::Expression::OffsetOf(_) => unsupported("`offsetof`"),
+            ast::Expression::VaArg(_) => unsupported("`va_arg`"),
+            ast::Expression::Statement(_) => unsupported("statement expression"),
         }
     }
 }
 
-pub(crate) fn translate_unit(
-    config: &Config,
-    provider: &dyn SourceProvider,
-    id: FileId,
-    decls: &[UnitDeclaration],
-) -> rg::Unit {
-    let mut translator = Translator::new(config, provider, id);
-    translator.visit_unit(decls);
+pub fn translate_unit(config: &Config, provider: &dyn SourceProvider, unit: &Unit) -> rg::Unit {
+    let mut translator = Translator::new(config, provider, unit.id);
+    translator.libraries = unit.libraries.iter().cloned().collect();
+    translator.visit_unit(&unit.declarations);
     translator.collect_opaque_structs();
+    // typedefs from other units are handled once they're all translated
+    symbols::collapse_function_pointers(std::slice::from_mut(&mut translator.unit));
+    symbols::override_types(
+        std::slice::from_mut(&mut translator.unit),
+        &config.type_overrides,
+    );
     translator.unit
 }
 
-pub(crate) fn prelude() -> &'static str {
+pub fn prelude() -> &'static str {
     use indoc::indoc;
     return indoc!(
         r#"
@@ -620,13 +1032,54 @@ pub(crate) fn prelude() -> &'static str {
     );
 }
 
-fn builtin<S: AsRef<str>>(s: S) -> rg::Type {
-    rg::Type::Name(rg::Identifier::name(s.as_ref()))
+/// `None` for functions returning `void`
+fn return_type(typ: rg::Type) -> Option<Box<rg::Type>> {
+    match typ {
+        rg::Type::Name(name) if name.value == VOID => None,
+        typ => Some(Box::new(typ)),
+    }
+}
+
+/// Names of the fields declared in a struct or union, not counting those
+/// of anonymous members
+fn field_names(declarations: &[Node<ast::StructDeclaration>]) -> HashSet<String> {
+    nodes(declarations)
+        .filter_map(|dtion| match dtion {
+            ast::StructDeclaration::Field(field) => Some(&field.node),
+            _ => None,
+        })
+        .flat_map(|field| nodes(&field.declarators[..]))
+        .filter_map(|sdtor| sdtor.declarator.as_ref())
+        .filter_map(|dtor| dtor.node.get_identifier())
+        .map(|id| id.name.clone())
+        .collect()
+}
+
+/// For a field without declarators, the kind of the anonymous struct or
+/// union it declares, if it's one
+fn anonymous_member_kind(field: &ast::StructField) -> Option<ast::StructKind> {
+    nodes(&field.specifiers[..]).find_map(|sq| match sq {
+        ast::SpecifierQualifier::TypeSpecifier(Node {
+            node: ast::TypeSpecifier::Struct(struty),
+            ..
+        }) if struty.node.identifier.is_none() => Some(struty.node.kind.node.clone()),
+        _ => None,
+    })
 }
 
-fn ctype(s: &str) -> rg::Type {
-    builtin(format!("::std::os::raw::c_{}", s))
+/// `struct_NAME` or `union_NAME`, depending on the keyword used
+fn struct_identifier(struty: &ast::StructType, name: &str) -> rg::Identifier {
+    match struty.kind.node {
+        ast::StructKind::Struct => rg::Identifier::struct_name(name),
+        ast::StructKind::Union => rg::Identifier::union_name(name),
+    }
+}
+
+const VOID: &str = "::core::ffi::c_void";
+
+fn builtin<S: AsRef<str>>(s: S) -> rg::Type {
+    rg::Type::Name(rg::Identifier::name(s.as_ref()))
 }
 
 #[cfg(test)]
-mod test_translator;
+pub(crate) mod test_translator;
@@ -38,6 +38,10 @@ static RUST_COOKED_KEYWORDS: Lazy<HashSet<String>> = Lazy::new(|| {
     set
 });
 
+pub fn is_keyword(s: &str) -> bool {
+    RUST_KEYWORDS.contains(s)
+}
+
 pub const INDENT: &str = "    "; // 4 spaces
 
 pub struct IndentedWriter<'a> {
@@ -131,6 +135,8 @@ impl fmt::Display for Repr {
     }
 }
 
+const DERIVE_COPY: &str = "#[derive(Clone, Copy)]";
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Unit {
     pub id: FileId,
@@ -159,8 +165,10 @@ impl fmt::Display for Unit {
 pub enum TopLevel {
     AliasDeclaration(AliasDeclaration),
     StructDeclaration(StructDeclaration),
+    UnionDeclaration(UnionDeclaration),
     EnumDeclaration(EnumDeclaration),
     FunctionDeclaration(FunctionDeclaration),
+    StaticDeclaration(StaticDeclaration),
     Constant(Constant),
 }
 
@@ -182,6 +190,12 @@ impl From<StructDeclaration> for TopLevel {
     }
 }
 
+impl From<UnionDeclaration> for TopLevel {
+    fn from(d: UnionDeclaration) -> Self {
+        Self::UnionDeclaration(d)
+    }
+}
+
 impl From<EnumDeclaration> for TopLevel {
     fn from(d: EnumDeclaration) -> Self {
         Self::EnumDeclaration(d)
@@ -194,6 +208,99 @@ impl From<FunctionDeclaration> for TopLevel {
     }
 }
 
+impl From<StaticDeclaration> for TopLevel {
+    fn from(d: StaticDeclaration) -> Self {
+        Self::StaticDeclaration(d)
+    }
+}
+
+impl TopLevel {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::AliasDeclaration(d) => &d.name.value,
+            Self::StructDeclaration(d) => &d.name.value,
+            Self::UnionDeclaration(d) => &d.name.value,
+            Self::EnumDeclaration(d) => &d.name.value,
+            Self::FunctionDeclaration(d) => &d.name.value,
+            Self::StaticDeclaration(d) => &d.name.value,
+            Self::Constant(c) => &c.name.value,
+        }
+    }
+
+    /// Calls `f` with the name of every type this declaration refers to
+    pub fn visit_type_names<'a>(&'a self, f: &mut dyn FnMut(&'a str)) {
+        match self {
+            Self::AliasDeclaration(d) => d.typ.visit_names(f),
+            Self::StructDeclaration(d) => {
+                for field in &d.fields {
+                    field.typ.visit_names(f);
+                }
+                for bitfield in &d.bitfields {
+                    bitfield.typ.visit_names(f);
+                }
+            }
+            Self::UnionDeclaration(d) => {
+                for field in &d.fields {
+                    field.typ.visit_names(f);
+                }
+                for bitfield in &d.bitfields {
+                    bitfield.typ.visit_names(f);
+                }
+            }
+            Self::EnumDeclaration(d) => {
+                for value in d.fields.iter().filter_map(|field| field.value.as_ref()) {
+                    value.visit_type_names(f);
+                }
+            }
+            Self::FunctionDeclaration(d) => {
+                for param in &d.params {
+                    param.typ.visit_names(f);
+                }
+                if let Some(ret) = &d.ret {
+                    ret.visit_names(f);
+                }
+            }
+            Self::StaticDeclaration(d) => d.typ.visit_names(f),
+            Self::Constant(c) => c.typ.visit_names(f),
+        }
+    }
+
+    /// Calls `f` with every type in this declaration, and the types they're
+    /// made of, so they can be rewritten
+    pub fn visit_types_mut(&mut self, f: &mut dyn FnMut(&mut Type)) {
+        match self {
+            Self::AliasDeclaration(d) => d.typ.visit_mut(f),
+            Self::StructDeclaration(d) => {
+                for field in &mut d.fields {
+                    field.typ.visit_mut(f);
+                }
+                for bitfield in &mut d.bitfields {
+                    bitfield.typ.visit_mut(f);
+                }
+            }
+            Self::UnionDeclaration(d) => {
+                for field in &mut d.fields {
+                    field.typ.visit_mut(f);
+                }
+                for bitfield in &mut d.bitfields {
+                    bitfield.typ.visit_mut(f);
+                }
+            }
+            Self::EnumDeclaration(_) => {}
+            Self::FunctionDeclaration(d) => {
+                for param in &mut d.params {
+                    param.typ.visit_mut(f);
+                }
+                if let Some(ret) = &mut d.ret {
+                    ret.visit_mut(f);
+                }
+            }
+            Self::StaticDeclaration(d) => d.typ.visit_mut(f),
+            Self::Constant(c) => c.typ.visit_mut(f),
+        }
+    }
+}
+
 impl fmt::Display for TopLevel {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -203,12 +310,18 @@ impl fmt::Display for TopLevel {
             Self::StructDeclaration(d) => {
                 write!(f, "{}", d)?;
             }
+            Self::UnionDeclaration(d) => {
+                write!(f, "{}", d)?;
+            }
             Self::EnumDeclaration(d) => {
                 write!(f, "{}", d)?;
             }
             Self::FunctionDeclaration(d) => {
                 write!(f, "{}", d)?;
             }
+            Self::StaticDeclaration(d) => {
+                write!(f, "{}", d)?;
+            }
             Self::Constant(c) => {
                 write!(f, "{}", c)?;
             }
@@ -259,6 +372,8 @@ impl fmt::Display for AliasDeclaration {
 pub struct StructDeclaration {
     pub name: Identifier,
     pub fields: Vec<StructField>,
+    /// Accessors for the bitfields packed into `fields`
+    pub bitfields: Vec<Bitfield>,
 }
 
 impl fmt::Display for StructDeclaration {
@@ -274,6 +389,7 @@ impl fmt::Display for StructDeclaration {
             )?;
         } else {
             writeln!(f, "{repr}", repr = Repr::C)?;
+            writeln!(f, "{derive}", derive = DERIVE_COPY)?;
             writeln!(
                 f,
                 "{vis} struct {name} {{",
@@ -287,7 +403,39 @@ impl fmt::Display for StructDeclaration {
                 }
             }
             writeln!(f, "}}")?;
+            write_bitfield_accessors(f, &self.name, &self.bitfields)?;
+        }
+        Ok(())
+    }
+}
+
+/// A C union. Its fields must be `Copy`, so structs, unions and enums all
+/// derive it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnionDeclaration {
+    pub name: Identifier,
+    pub fields: Vec<StructField>,
+    pub bitfields: Vec<Bitfield>,
+}
+
+impl fmt::Display for UnionDeclaration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{repr}", repr = Repr::C)?;
+        writeln!(f, "{derive}", derive = DERIVE_COPY)?;
+        writeln!(
+            f,
+            "{vis} union {name} {{",
+            vis = Visi::Pub,
+            name = self.name
+        )?;
+        {
+            let f = &mut f.indented();
+            for field in &self.fields {
+                writeln!(f, "{},", field)?;
+            }
         }
+        writeln!(f, "}}")?;
+        write_bitfield_accessors(f, &self.name, &self.bitfields)?;
         Ok(())
     }
 }
@@ -296,6 +444,9 @@ impl fmt::Display for StructDeclaration {
 pub struct StructField {
     pub name: Identifier,
     pub typ: Type,
+    /// Width in bits, for bitfields that haven't been allocated to a storage
+    /// unit yet. Unnamed bitfields (`int : 3`) have an empty name.
+    pub bits: Option<u32>,
 }
 
 impl fmt::Display for StructField {
@@ -304,6 +455,136 @@ impl fmt::Display for StructField {
     }
 }
 
+/// A bitfield, read and written through methods, since Rust has no
+/// bitfields. Several of them share a `storage` field of an unsigned integer
+/// type.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Bitfield {
+    pub name: Identifier,
+    /// Declared type, returned by the getter
+    pub typ: Type,
+    pub kind: BitfieldKind,
+    pub storage: Identifier,
+    /// Size of the storage unit, in bits
+    pub storage_bits: u32,
+    /// Position of the lowest bit in the storage unit
+    pub offset: u32,
+    pub width: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitfieldKind {
+    /// Signed fields are sign-extended when read
+    Integer {
+        signed: bool,
+    },
+    Bool,
+    /// Enums wrap a `u32`. They're built with a struct expression, which
+    /// works through typedefs too.
+    Enum,
+}
+
+impl Bitfield {
+    fn mask(&self) -> String {
+        format!("{:#x}", u64::MAX >> (64 - self.width))
+    }
+}
+
+impl fmt::Display for Bitfield {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (bits, offset, width) = (self.storage_bits, self.offset, self.width);
+
+        writeln!(
+            f,
+            "{vis} fn {name}(&self) -> {typ} {{",
+            vis = Visi::Pub,
+            name = self.name,
+            typ = self.typ
+        )?;
+        {
+            let f = &mut f.indented();
+            match self.kind {
+                BitfieldKind::Integer { signed: true } => writeln!(
+                    f,
+                    "(((self.{storage} << {left}) as i{bits}) >> {right}) as {typ}",
+                    storage = self.storage,
+                    left = bits - offset - width,
+                    bits = bits,
+                    right = bits - width,
+                    typ = self.typ
+                )?,
+                BitfieldKind::Integer { signed: false } => writeln!(
+                    f,
+                    "((self.{storage} >> {offset}) & {mask}) as {typ}",
+                    storage = self.storage,
+                    offset = offset,
+                    mask = self.mask(),
+                    typ = self.typ
+                )?,
+                BitfieldKind::Bool => writeln!(
+                    f,
+                    "(self.{storage} >> {offset}) & {mask} != 0",
+                    storage = self.storage,
+                    offset = offset,
+                    mask = self.mask(),
+                )?,
+                BitfieldKind::Enum => writeln!(
+                    f,
+                    "{typ} {{ 0: ((self.{storage} >> {offset}) & {mask}) as u32 }}",
+                    typ = self.typ,
+                    storage = self.storage,
+                    offset = offset,
+                    mask = self.mask(),
+                )?,
+            }
+        }
+        writeln!(f, "}}")?;
+
+        writeln!(
+            f,
+            "{vis} fn set_{name}(&mut self, value: {typ}) {{",
+            vis = Visi::Pub,
+            name = self.name.value,
+            typ = self.typ
+        )?;
+        {
+            let f = &mut f.indented();
+            let value = match self.kind {
+                BitfieldKind::Enum => "value.0",
+                _ => "value",
+            };
+            writeln!(f, "let value = {} as u{};", value, bits)?;
+            writeln!(
+                f,
+                "self.{storage} = (self.{storage} & !({mask} << {offset})) | ((value & {mask}) << {offset});",
+                storage = self.storage,
+                mask = self.mask(),
+                offset = offset,
+            )?;
+        }
+        writeln!(f, "}}")?;
+        Ok(())
+    }
+}
+
+fn write_bitfield_accessors(
+    f: &mut fmt::Formatter<'_>,
+    name: &Identifier,
+    bitfields: &[Bitfield],
+) -> fmt::Result {
+    if bitfields.is_empty() {
+        return Ok(());
+    }
+    writeln!(f, "impl {name} {{", name = name)?;
+    {
+        let f = &mut f.indented();
+        for bitfield in bitfields {
+            write!(f, "{}", bitfield)?;
+        }
+    }
+    writeln!(f, "}}")
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct EnumDeclaration {
     pub name: Identifier,
@@ -313,6 +594,7 @@ pub struct EnumDeclaration {
 impl fmt::Display for EnumDeclaration {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "{repr}", repr = Repr::Transparent)?;
+        writeln!(f, "{derive}", derive = DERIVE_COPY)?;
         writeln!(
             f,
             "{vis} struct {name}(pub u32);",
@@ -372,13 +654,24 @@ impl<'a> fmt::Display for EnumFieldTuple<'a> {
 #[derive(Debug, PartialEq, Eq)]
 pub struct FunctionDeclaration {
     pub name: Identifier,
+    /// Libraries to link against, from `#pragma comment(lib, ...)`
+    pub links: Vec<String>,
     pub params: Vec<FunctionParam>,
+    /// Takes more arguments after `params`, ie. `int printf(const char *, ...)`.
+    /// There's no mapping of `va_list` per architecture: MSVC's `<vadefs.h>`
+    /// declares it as a `char *` on both x86 and x86-64, which translates as
+    /// is, and GCC's `__builtin_va_list` isn't handled.
+    pub variadic: bool,
     pub ret: Option<Type>,
+    pub abi: Abi,
 }
 
 impl fmt::Display for FunctionDeclaration {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "extern {c:?} {{", c = "C")?;
+        for link in &self.links {
+            writeln!(f, "#[link(name = {link:?})]", link = link)?;
+        }
+        writeln!(f, "extern {abi} {{", abi = self.abi)?;
         {
             let f = &mut f.indented();
             write!(f, "{vis} fn {name} (", vis = Visi::Pub, name = self.name,)?;
@@ -388,6 +681,11 @@ impl fmt::Display for FunctionDeclaration {
                 }
                 write!(f, "{param}", param = param)?;
             }
+            match (self.variadic, self.params.is_empty()) {
+                (true, true) => write!(f, "...")?,
+                (true, false) => write!(f, ", ...")?,
+                (false, _) => {}
+            }
             write!(f, ")")?;
             if let Some(ret) = self.ret.as_ref() {
                 write!(f, " -> {ret}", ret = ret)?;
@@ -400,6 +698,47 @@ impl fmt::Display for FunctionDeclaration {
     }
 }
 
+/// Global variable defined in a library, ie. `extern const IID IID_IUnknown;`
+#[derive(Debug, PartialEq, Eq)]
+pub struct StaticDeclaration {
+    pub name: Identifier,
+    /// Libraries to link against, from `#pragma comment(lib, ...)`
+    pub links: Vec<String>,
+    pub typ: Type,
+    pub mutable: bool,
+    /// Declared `__declspec(dllimport)`, so it's only reachable through the
+    /// import library of a DLL
+    pub dllimport: bool,
+}
+
+impl fmt::Display for StaticDeclaration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // rustc only reaches statics through `__imp_` pointers when their
+        // extern block links a dylib (the default kind), and data that isn't
+        // dllimport has no such pointer.
+        if self.dllimport {
+            for link in &self.links {
+                writeln!(f, "#[link(name = {link:?})]", link = link)?;
+            }
+        }
+        writeln!(f, "extern \"C\" {{")?;
+        {
+            let f = &mut f.indented();
+            writeln!(
+                f,
+                "{vis} static {mutability}{name}: {typ};",
+                vis = Visi::Pub,
+                mutability = if self.mutable { "mut " } else { "" },
+                name = self.name,
+                typ = self.typ,
+            )?;
+        }
+        writeln!(f, "}}")?;
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct FunctionParam {
     pub name: Identifier,
@@ -412,46 +751,148 @@ impl fmt::Display for FunctionParam {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Type {
     Name(Identifier),
     Function(FunctionType),
-    Pointer { konst: bool, inner: Box<Type> },
+    Pointer {
+        konst: bool,
+        inner: Box<Type>,
+    },
+    /// Fixed-size array, ie. `[WCHAR; 260]`
+    Array {
+        inner: Box<Type>,
+        len: Box<Expr>,
+    },
+}
+
+impl Type {
+    /// Calls `f` with every name this type is made of
+    pub fn visit_names<'a>(&'a self, f: &mut dyn FnMut(&'a str)) {
+        match self {
+            Self::Name(name) => f(&name.value),
+            Self::Pointer { inner, .. } => inner.visit_names(f),
+            Self::Array { inner, len } => {
+                inner.visit_names(f);
+                len.visit_names(f);
+            }
+            Self::Function(ft) => {
+                for param in &ft.params {
+                    param.visit_names(f);
+                }
+                if let Some(ret) = &ft.ret {
+                    ret.visit_names(f);
+                }
+            }
+        }
+    }
+
+    /// Calls `f` with this type, then with every type it's made of, so they
+    /// can be rewritten
+    pub fn visit_mut(&mut self, f: &mut dyn FnMut(&mut Type)) {
+        f(self);
+        match self {
+            Self::Name(_) => {}
+            Self::Pointer { inner, .. } | Self::Array { inner, .. } => inner.visit_mut(f),
+            Self::Function(ft) => {
+                for param in &mut ft.params {
+                    param.visit_mut(f);
+                }
+                if let Some(ret) = &mut ft.ret {
+                    ret.visit_mut(f);
+                }
+            }
+        }
+    }
 }
 
 impl fmt::Display for Type {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Name(name) => write!(f, "{}", name),
+            // a Rust function pointer is already a pointer
+            Self::Pointer { inner, .. } if matches!(inner.as_ref(), Self::Function(_)) => {
+                write!(f, "{}", inner)
+            }
             Self::Pointer { konst, inner } => match konst {
                 true => write!(f, "*const {}", inner),
                 false => write!(f, "*mut {}", inner),
             },
+            Self::Array { inner, len } => {
+                // compound expressions are wrapped in parentheses as a
+                // whole, which rustc would warn about here
+                let len = len.as_array_len().to_string();
+                let len = match len.strip_prefix('(') {
+                    Some(len) => len.strip_suffix(')').unwrap_or(len),
+                    None => &len,
+                };
+                write!(f, "[{}; {}]", inner, len)
+            }
             Type::Function(ft) => write!(f, "{}", ft),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FunctionType {
     pub params: Vec<Type>,
+    pub variadic: bool,
+    pub ret: Option<Box<Type>>,
+    pub abi: Abi,
 }
 
+/// Calling convention of a function, as the ABI string of an `extern` block
+/// or function pointer type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Abi {
+    C,
+    /// `stdcall` on 32-bit Windows, `C` everywhere else
+    System,
+    Fastcall,
+    Thiscall,
+    /// Requires the unstable `abi_vectorcall` feature
+    Vectorcall,
+}
+
+impl fmt::Display for Abi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::C => "C",
+            Self::System => "system",
+            Self::Fastcall => "fastcall",
+            Self::Thiscall => "thiscall",
+            Self::Vectorcall => "vectorcall",
+        };
+        write!(f, "{:?}", name)
+    }
+}
+
+/// Function pointers can be null in C, so they're wrapped in an `Option`,
+/// which has the same layout.
 impl fmt::Display for FunctionType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "extern {c:?} fn(", c = "C")?;
+        write!(f, "Option<unsafe extern {abi} fn(", abi = self.abi)?;
         for (i, param) in self.params.iter().enumerate() {
             if i != 0 {
                 write!(f, ", ")?;
             }
             write!(f, "{}", param)?;
         }
+        match (self.variadic, self.params.is_empty()) {
+            (true, true) => write!(f, "...")?,
+            (true, false) => write!(f, ", ...")?,
+            (false, _) => {}
+        }
         write!(f, ")")?;
+        if let Some(ret) = self.ret.as_ref() {
+            write!(f, " -> {}", ret)?;
+        }
+        write!(f, ">")?;
         Ok(())
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Identifier {
     pub value: String,
 }
@@ -496,27 +937,144 @@ impl Identifier {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Expr {
     Constant(ast::Constant),
+    /// Result of evaluating a constant expression
+    Integer(u64),
     BinaryOperator(ast::BinaryOperator, Box<Expr>, Box<Expr>),
+    /// One of `-`, `+`, `~` and `!`
+    UnaryOperator(ast::UnaryOperator, Box<Expr>),
     Cast(Type, Box<Expr>),
     SizeOf(Type),
     AlignOf(Type),
     Identifier(String),
+    /// An expression with no Rust equivalent, ie. a function call, which
+    /// is displayed as a `compile_error!` naming it
+    Unsupported(String),
 }
 
-struct EnumExpr<'a>(&'a Expr);
+/// Displays an expression as a Rust constant expression
+struct ConstExpr<'a> {
+    expr: &'a Expr,
+    context: ConstContext,
+}
+
+#[derive(Clone, Copy)]
+enum ConstContext {
+    /// Value of an enum variant: identifiers are sibling variants
+    Enum,
+    /// Length of an array: identifiers are constants, and the result
+    /// must be a `usize`
+    ArrayLen,
+}
 
 impl Expr {
-    fn as_enum_expr(&self) -> EnumExpr {
-        EnumExpr(self)
+    /// Calls `f` with the name of every type used in casts and `sizeof`
+    pub fn visit_type_names<'a>(&'a self, f: &mut dyn FnMut(&'a str)) {
+        self.visit(false, f)
+    }
+
+    /// Calls `f` with the name of every type and constant used
+    pub fn visit_names<'a>(&'a self, f: &mut dyn FnMut(&'a str)) {
+        self.visit(true, f)
+    }
+
+    fn visit<'a>(&'a self, identifiers: bool, f: &mut dyn FnMut(&'a str)) {
+        match self {
+            Self::Constant(_) | Self::Integer(_) | Self::Unsupported(_) => {}
+            Self::Identifier(name) => {
+                if identifiers {
+                    f(name)
+                }
+            }
+            Self::BinaryOperator(_, lhs, rhs) => {
+                lhs.visit(identifiers, f);
+                rhs.visit(identifiers, f);
+            }
+            Self::UnaryOperator(_, expr) => expr.visit(identifiers, f),
+            Self::Cast(ty, expr) => {
+                ty.visit_names(f);
+                expr.visit(identifiers, f);
+            }
+            Self::SizeOf(ty) | Self::AlignOf(ty) => ty.visit_names(f),
+        }
+    }
+
+    /// Evaluates integer arithmetic, ie. `(2 * 130)` to `260`. Returns
+    /// `None` for expressions that refer to names or types. Negative
+    /// results wrap around, as they would converted to an unsigned type.
+    pub fn evaluate(&self) -> Option<u64> {
+        self.evaluate_with(&|_| None)
+    }
+
+    /// Like `evaluate`, with `size_of` giving the size of the types in
+    /// `sizeof` expressions, ie. `DataModel::size_of`.
+    pub fn evaluate_with(&self, size_of: &dyn Fn(&Type) -> Option<u64>) -> Option<u64> {
+        use ast::BinaryOperator as BO;
+        use ast::UnaryOperator as UO;
+        match self {
+            Self::Integer(n) => Some(*n),
+            Self::Constant(ast::Constant::Integer(ast::Integer { base, number, .. })) => {
+                let radix = match base {
+                    ast::IntegerBase::Decimal => 10,
+                    ast::IntegerBase::Octal => 8,
+                    ast::IntegerBase::Hexadecimal => 16,
+                };
+                u64::from_str_radix(number, radix).ok()
+            }
+            Self::BinaryOperator(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.evaluate_with(size_of)?, rhs.evaluate_with(size_of)?);
+                match op {
+                    BO::Multiply => lhs.checked_mul(rhs),
+                    BO::Divide => lhs.checked_div(rhs),
+                    BO::Modulo => lhs.checked_rem(rhs),
+                    BO::Plus => lhs.checked_add(rhs),
+                    BO::Minus => lhs.checked_sub(rhs),
+                    BO::ShiftLeft => lhs.checked_shl(rhs as u32),
+                    BO::ShiftRight => lhs.checked_shr(rhs as u32),
+                    BO::BitwiseAnd => Some(lhs & rhs),
+                    BO::BitwiseXor => Some(lhs ^ rhs),
+                    BO::BitwiseOr => Some(lhs | rhs),
+                    _ => None,
+                }
+            }
+            Self::UnaryOperator(op, expr) => {
+                let n = expr.evaluate_with(size_of)?;
+                match op {
+                    UO::Minus => Some(n.wrapping_neg()),
+                    UO::Plus => Some(n),
+                    UO::Complement => Some(!n),
+                    UO::Negate => Some((n == 0) as u64),
+                    _ => None,
+                }
+            }
+            Self::Cast(_, expr) => expr.evaluate_with(size_of),
+            Self::SizeOf(ty) => size_of(ty),
+            _ => None,
+        }
+    }
+
+    fn as_enum_expr(&self) -> ConstExpr<'_> {
+        ConstExpr {
+            expr: self,
+            context: ConstContext::Enum,
+        }
+    }
+
+    fn as_array_len(&self) -> ConstExpr<'_> {
+        ConstExpr {
+            expr: self,
+            context: ConstContext::ArrayLen,
+        }
     }
 }
 
-impl<'a> fmt::Display for EnumExpr<'a> {
+impl<'a> fmt::Display for ConstExpr<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.0 {
+        let context = self.context;
+        let sub = |expr| ConstExpr { expr, context };
+        match self.expr {
             Expr::Constant(c) => match c {
                 ast::Constant::Integer(ast::Integer { base, number, .. }) => {
                     match base {
@@ -535,9 +1093,10 @@ impl<'a> fmt::Display for EnumExpr<'a> {
                 }
                 ast::Constant::Character(_) => {}
             },
+            Expr::Integer(n) => write!(f, "{}", n)?,
             Expr::BinaryOperator(op, lhs, rhs) => {
-                let lhs = lhs.as_enum_expr();
-                let rhs = rhs.as_enum_expr();
+                let lhs = sub(lhs);
+                let rhs = sub(rhs);
                 match op {
                     ast::BinaryOperator::Index => write!(f, "({}[{}])", lhs, rhs)?,
                     ast::BinaryOperator::Multiply => write!(f, "({} * {})", lhs, rhs)?,
@@ -571,17 +1130,45 @@ impl<'a> fmt::Display for EnumExpr<'a> {
                     ast::BinaryOperator::AssignBitwiseOr => todo!(),
                 }
             }
+            Expr::UnaryOperator(op, expr) => {
+                let expr = sub(expr);
+                match (op, context) {
+                    // enum values are cast to `u32` as a whole, which would
+                    // make rustc infer an unsigned operand
+                    (ast::UnaryOperator::Minus, ConstContext::Enum) => {
+                        write!(f, "(-({} as i64))", expr)?
+                    }
+                    (ast::UnaryOperator::Minus, ConstContext::ArrayLen) => {
+                        write!(f, "(-{})", expr)?
+                    }
+                    (ast::UnaryOperator::Complement, _) => write!(f, "(!{})", expr)?,
+                    (ast::UnaryOperator::Negate, ConstContext::Enum) => {
+                        write!(f, "(({} == 0) as u32)", expr)?
+                    }
+                    (ast::UnaryOperator::Negate, ConstContext::ArrayLen) => {
+                        write!(f, "(({} == 0) as usize)", expr)?
+                    }
+                    _ => write!(f, "{}", expr)?,
+                }
+            }
             Expr::SizeOf(e) => {
                 write!(f, "::core::mem::size_of::<{}>()", e)?;
             }
             Expr::AlignOf(e) => {
                 write!(f, "::core::mem::align_of::<{}>()", e)?;
             }
-            Expr::Cast(ty, expr) => {
-                write!(f, "({expr} as {ty})", expr = expr.as_enum_expr(), ty = ty)?;
-            }
-            Expr::Identifier(name) => {
-                write!(f, "Self::{name}.0", name = name)?;
+            Expr::Cast(ty, expr) => match context {
+                ConstContext::Enum => {
+                    write!(f, "({expr} as {ty})", expr = sub(expr), ty = ty)?;
+                }
+                ConstContext::ArrayLen => write!(f, "({} as usize)", sub(expr))?,
+            },
+            Expr::Identifier(name) => match context {
+                ConstContext::Enum => write!(f, "Self::{name}.0", name = name)?,
+                ConstContext::ArrayLen => write!(f, "({name} as usize)", name = name)?,
+            },
+            Expr::Unsupported(what) => {
+                write!(f, "compile_error!(\"unsupported C expression: {}\")", what)?
             }
         };
         Ok(())
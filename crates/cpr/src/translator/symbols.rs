@@ -0,0 +1,254 @@
+//! Which unit declares each name, across a whole translation.
+
+use super::rg;
+use crate::frontend::FileId;
+use indexmap::IndexSet;
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    /// Unit that owns each type, alias, constant and function name. When
+    /// several units declare the same name, the first one owns it.
+    owners: HashMap<String, FileId>,
+}
+
+impl SymbolTable {
+    pub fn new<'a, I: IntoIterator<Item = &'a rg::Unit>>(units: I) -> Self {
+        let mut owners = HashMap::new();
+        for unit in units {
+            for tl in &unit.toplevels {
+                owners.entry(tl.name().to_string()).or_insert(unit.id);
+            }
+        }
+        Self { owners }
+    }
+
+    pub fn owner(&self, name: &str) -> Option<FileId> {
+        self.owners.get(name).copied()
+    }
+
+    /// Returns the names `unit` refers to but doesn't declare, along with
+    /// the unit that owns them, in order of first use. Names that aren't
+    /// declared anywhere (ie. builtins) are left out.
+    pub fn imports<'a>(&self, unit: &'a rg::Unit) -> Vec<(&'a str, FileId)> {
+        let local: IndexSet<&str> = unit.toplevels.iter().map(|tl| tl.name()).collect();
+
+        let mut names = IndexSet::new();
+        for tl in &unit.toplevels {
+            tl.visit_type_names(&mut |name| {
+                if !local.contains(name) {
+                    names.insert(name);
+                }
+            });
+        }
+
+        names
+            .into_iter()
+            .filter_map(|name| match self.owner(name) {
+                Some(id) => Some((name, id)),
+                None => {
+                    if !name.starts_with("::") {
+                        log::debug!("unresolved name {:?}", name);
+                    }
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Two units declaring the same name differently
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub name: String,
+    /// Unit whose declaration was kept
+    pub kept: FileId,
+    /// Unit whose declaration was dropped
+    pub dropped: FileId,
+}
+
+/// Removes redeclarations across `units`, so each name is declared once, in
+/// the first unit that declares it. Opaque structs give way to their full
+/// definition. Returns the redeclarations that differ from the declaration
+/// that was kept, which were dropped too.
+pub fn deduplicate(units: &mut [rg::Unit]) -> Vec<Conflict> {
+    let mut first: HashMap<&str, (usize, usize)> = HashMap::new();
+    let mut removed = HashSet::new();
+    let mut links = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for (ui, unit) in units.iter().enumerate() {
+        for (ti, tl) in unit.toplevels.iter().enumerate() {
+            let (fu, ft) = match first.entry(tl.name()) {
+                Entry::Vacant(e) => {
+                    e.insert((ui, ti));
+                    continue;
+                }
+                Entry::Occupied(e) => *e.get(),
+            };
+            let prev = &units[fu].toplevels[ft];
+
+            if is_opaque(prev) && is_struct(tl) {
+                removed.insert((fu, ft));
+                first.insert(tl.name(), (ui, ti));
+                continue;
+            }
+            removed.insert((ui, ti));
+
+            use rg::TopLevel::FunctionDeclaration as FD;
+            match (prev, tl) {
+                _ if is_opaque(tl) && is_struct(prev) => {}
+                (FD(a), FD(b))
+                    if (&a.params, a.variadic, &a.ret, a.abi)
+                        == (&b.params, b.variadic, &b.ret, b.abi) =>
+                {
+                    // same function, maybe with other `#pragma comment(lib)`
+                    links.push(((fu, ft), b.links.clone()));
+                }
+                (a, b) if a == b => {}
+                _ => conflicts.push(Conflict {
+                    name: tl.name().to_string(),
+                    kept: units[fu].id,
+                    dropped: unit.id,
+                }),
+            }
+        }
+    }
+
+    for ((ui, ti), extra) in links {
+        if let rg::TopLevel::FunctionDeclaration(fd) = &mut units[ui].toplevels[ti] {
+            for link in extra {
+                if !fd.links.contains(&link) {
+                    fd.links.push(link);
+                }
+            }
+        }
+    }
+    for (ui, unit) in units.iter_mut().enumerate() {
+        let mut ti = 0;
+        unit.toplevels.retain(|_| {
+            ti += 1;
+            !removed.contains(&(ui, ti - 1))
+        });
+    }
+    conflicts
+}
+
+fn is_struct(tl: &rg::TopLevel) -> bool {
+    matches!(
+        tl,
+        rg::TopLevel::StructDeclaration(_) | rg::TopLevel::UnionDeclaration(_)
+    )
+}
+
+fn is_opaque(tl: &rg::TopLevel) -> bool {
+    matches!(tl, rg::TopLevel::StructDeclaration(sd) if sd.fields.is_empty())
+}
+
+/// Makes parameters whose type is an array typedef decay to pointers, like
+/// parameters declared as arrays do, ie. `void Fill(MATRIX m)` takes a
+/// `*mut [c_int; 4]` when `MATRIX` is `int[4][4]`. Typedefs are looked up
+/// across all `units`.
+pub fn decay_array_params(units: &mut [rg::Unit]) {
+    let mut elements = HashMap::new();
+    for unit in units.iter() {
+        for tl in &unit.toplevels {
+            if let rg::TopLevel::AliasDeclaration(ad) = tl {
+                if let rg::Type::Array { inner, .. } = &ad.typ {
+                    elements.insert(ad.name.value.clone(), inner.as_ref().clone());
+                }
+            }
+        }
+    }
+    if elements.is_empty() {
+        return;
+    }
+
+    let decay = |typ: &mut rg::Type| {
+        if let rg::Type::Name(name) = typ {
+            if let Some(element) = elements.get(&name.value) {
+                *typ = rg::Type::Pointer {
+                    konst: false,
+                    inner: Box::new(element.clone()),
+                };
+            }
+        }
+    };
+    for unit in units.iter_mut() {
+        for tl in &mut unit.toplevels {
+            if let rg::TopLevel::FunctionDeclaration(fd) = tl {
+                fd.params.iter_mut().for_each(|param| decay(&mut param.typ));
+            }
+            tl.visit_types_mut(&mut |typ| {
+                if let rg::Type::Function(ft) = typ {
+                    ft.params.iter_mut().for_each(decay);
+                }
+            });
+        }
+    }
+}
+
+/// Uses existing Rust types instead of translating the types named in
+/// `overrides`, ie. `GUID = "::winapi::shared::guiddef::GUID"`. Their
+/// declarations are dropped, and references to them replaced.
+pub fn override_types(units: &mut [rg::Unit], overrides: &HashMap<String, String>) {
+    if overrides.is_empty() {
+        return;
+    }
+
+    for unit in units.iter_mut() {
+        unit.toplevels
+            .retain(|tl| !(is_type(tl) && overrides.contains_key(tl.name())));
+        for tl in &mut unit.toplevels {
+            tl.visit_types_mut(&mut |typ| {
+                if let rg::Type::Name(name) = typ {
+                    if let Some(path) = overrides.get(&name.value) {
+                        *typ = rg::Type::Name(rg::Identifier::name(path));
+                    }
+                }
+            });
+        }
+    }
+}
+
+fn is_type(tl: &rg::TopLevel) -> bool {
+    matches!(
+        tl,
+        rg::TopLevel::AliasDeclaration(_) | rg::TopLevel::EnumDeclaration(_)
+    ) || is_struct(tl)
+}
+
+/// Rust has no function types, only function pointers, so typedefs of
+/// function types are function pointers already. Pointers to them are
+/// replaced with the typedef, ie. `PEXCEPTION_ROUTINE` is an alias of
+/// `EXCEPTION_ROUTINE` when it's declared as `EXCEPTION_ROUTINE *`.
+/// Typedefs are looked up across all `units`.
+pub fn collapse_function_pointers(units: &mut [rg::Unit]) {
+    let functions: HashSet<String> = units
+        .iter()
+        .flat_map(|unit| &unit.toplevels)
+        .filter_map(|tl| match tl {
+            rg::TopLevel::AliasDeclaration(ad) if matches!(ad.typ, rg::Type::Function(_)) => {
+                Some(ad.name.value.clone())
+            }
+            _ => None,
+        })
+        .collect();
+    if functions.is_empty() {
+        return;
+    }
+
+    for unit in units.iter_mut() {
+        for tl in &mut unit.toplevels {
+            tl.visit_types_mut(&mut |typ| {
+                if let rg::Type::Pointer { inner, .. } = typ {
+                    if let rg::Type::Name(name) = inner.as_ref() {
+                        if functions.contains(&name.value) {
+                            *typ = rg::Type::Name(name.clone());
+                        }
+                    }
+                }
+            });
+        }
+    }
+}
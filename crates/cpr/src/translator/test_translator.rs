@@ -14,7 +14,9 @@ trait UnitExtension {
     fn must_have_alias<N: AsRef<str>>(&self, name: N, f: &dyn Fn(&rg::AliasDeclaration));
     fn must_have_enum<N: AsRef<str>>(&self, name: N, f: &dyn Fn(&rg::EnumDeclaration));
     fn must_have_struct<N: AsRef<str>>(&self, name: N, f: &dyn Fn(&rg::StructDeclaration));
+    fn must_have_union<N: AsRef<str>>(&self, name: N, f: &dyn Fn(&rg::UnionDeclaration));
     fn must_have_function<N: AsRef<str>>(&self, name: N, f: &dyn Fn(&rg::FunctionDeclaration));
+    fn must_have_static<N: AsRef<str>>(&self, name: N, f: &dyn Fn(&rg::StaticDeclaration));
     fn must_have_constant<N: AsRef<str>>(&self, name: N, f: &dyn Fn(&rg::Constant));
 
     fn must_have_alias_count(&self, count: usize);
@@ -77,6 +79,24 @@ impl UnitExtension for rg::Unit {
         f(d);
     }
 
+    fn must_have_union<N: AsRef<str>>(&self, name: N, f: &dyn Fn(&rg::UnionDeclaration)) {
+        let name = name.as_ref();
+        let d = self
+            .toplevels
+            .iter()
+            .filter_map(|tl| {
+                if let rg::TopLevel::UnionDeclaration(d) = tl {
+                    if d.name.value == name {
+                        return Some(d);
+                    }
+                };
+                None
+            })
+            .next()
+            .unwrap_or_else(|| panic!("should have a union with name {:?}", name));
+        f(d);
+    }
+
     fn must_have_function<N: AsRef<str>>(&self, name: N, f: &dyn Fn(&rg::FunctionDeclaration)) {
         let name = name.as_ref();
         let d = self
@@ -95,6 +115,24 @@ impl UnitExtension for rg::Unit {
         f(d);
     }
 
+    fn must_have_static<N: AsRef<str>>(&self, name: N, f: &dyn Fn(&rg::StaticDeclaration)) {
+        let name = name.as_ref();
+        let d = self
+            .toplevels
+            .iter()
+            .filter_map(|tl| {
+                if let rg::TopLevel::StaticDeclaration(d) = tl {
+                    if d.name.value == name {
+                        return Some(d);
+                    }
+                };
+                None
+            })
+            .next()
+            .unwrap_or_else(|| panic!("should have a static with name {:?}", name));
+        f(d);
+    }
+
     fn must_have_constant<N: AsRef<str>>(&self, name: N, f: &dyn Fn(&rg::Constant)) {
         let name = name.as_ref();
         let d = self
@@ -224,6 +262,25 @@ impl StructExtension for rg::StructDeclaration {
     }
 }
 
+impl StructExtension for rg::UnionDeclaration {
+    fn must_have_field(&self, name: &str, f: &dyn Fn(&rg::StructField)) {
+        let field = self
+            .fields
+            .iter()
+            .find(|f| f.name.value == name)
+            .unwrap_or_else(|| {
+                panic!(
+                    "union {:?} should have field with name {:?}",
+                    self.name.value, name
+                )
+            });
+        f(field);
+    }
+    fn must_be_opaque(&self) {
+        panic!("union {} can't be opaque", self.name.value);
+    }
+}
+
 trait FunctionDeclarationExtension {
     fn must_have_param(&self, name: &str, f: &dyn Fn(&rg::FunctionParam));
 }
@@ -247,6 +304,7 @@ impl FunctionDeclarationExtension for rg::FunctionDeclaration {
 trait StringExtension {
     fn struct_name(self) -> String;
     fn enum_name(self) -> String;
+    fn union_name(self) -> String;
     fn const_pointer_name(self) -> String;
     fn mut_pointer_name(self) -> String;
     fn ctype(self) -> String;
@@ -259,14 +317,29 @@ impl<'a> StringExtension for &'a str {
     fn enum_name(self) -> String {
         rg::Identifier::enum_name(self).value
     }
+    fn union_name(self) -> String {
+        rg::Identifier::union_name(self).value
+    }
     fn const_pointer_name(self) -> String {
         format!("*const {}", self)
     }
     fn mut_pointer_name(self) -> String {
         format!("*mut {}", self)
     }
+    /// Fixed-width type for a C type on LLP64, named as in `c_long`
     fn ctype(self) -> String {
-        format!("::std::os::raw::c_{}", self)
+        match self {
+            "char" | "schar" => "i8",
+            "uchar" => "u8",
+            "short" => "i16",
+            "ushort" => "u16",
+            "int" | "long" => "i32",
+            "uint" | "ulong" => "u32",
+            "longlong" => "i64",
+            "ulonglong" => "u64",
+            _ => panic!("not a C integer type: {:?}", self),
+        }
+        .to_string()
     }
 }
 
@@ -341,14 +414,14 @@ impl ExprExtension for rg::Expr {
     }
 }
 
-struct TestSourceProvider {
-    files: HashMap<PathBuf, String>,
+pub(crate) struct TestSourceProvider {
+    pub(crate) files: HashMap<PathBuf, String>,
     path_to_id: HashMap<FilePath, FileId>,
     id_to_info: HashMap<FileId, FileInfo>,
 }
 
 impl TestSourceProvider {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             files: Default::default(),
             path_to_id: Default::default(),
@@ -390,9 +463,13 @@ impl SourceProvider for TestSourceProvider {
         let info = self.id_to_info.get(&id).ok_or(Error::UnknownFileId)?;
         Ok(self.files.get(&info.path.rel_path).unwrap().clone())
     }
+    fn exists(&self, _working_dir: &SourceDir, include: &Include) -> bool {
+        let inc_path: &Path = include.as_ref();
+        self.files.contains_key(inc_path)
+    }
 }
 
-fn parse_units_with(provider: Box<dyn SourceProvider>, ctx: Context, env: Env) -> Vec<rg::Unit> {
+pub(crate) fn parse_root(provider: Box<dyn SourceProvider>, ctx: Context, env: Env) -> Parser {
     let mut parser = Parser::new(provider, ctx, env);
     let dir = SourceDir {
         pkg: "root".into(),
@@ -403,20 +480,24 @@ fn parse_units_with(provider: Box<dyn SourceProvider>, ctx: Context, env: Env) -
         .resolve(&mut parser.idgen, &dir, &Include::Quoted("root.h".into()))
         .unwrap();
     parser.parse_file(id).unwrap();
+    parser
+}
 
-    let config = Config { arch: Arch::X86_64 };
+fn parse_units_with(
+    provider: Box<dyn SourceProvider>,
+    ctx: Context,
+    env: Env,
+    arch: Arch,
+) -> Vec<rg::Unit> {
+    let parser = parse_root(provider, ctx, env);
+    let config = Config::new(arch);
 
     parser
         .ordered_files
         .iter()
         .map(|inc| {
             let unit = parser.units.get(inc).unwrap();
-            translate_unit(
-                &config,
-                parser.provider.as_ref(),
-                unit.id,
-                &unit.declarations[..],
-            )
+            translate_unit(&config, parser.provider.as_ref(), unit)
         })
         .collect()
 }
@@ -424,18 +505,22 @@ fn parse_units_with(provider: Box<dyn SourceProvider>, ctx: Context, env: Env) -
 fn parse_units(provider: Box<dyn SourceProvider>) -> Vec<rg::Unit> {
     let ctx = Context::new();
     let env = Env::with_msvc();
-    parse_units_with(provider, ctx, env)
+    parse_units_with(provider, ctx, env, Arch::X86_64)
 }
 
 fn parse_unit(input: &str) -> rg::Unit {
+    parse_unit_for(Arch::X86_64, input)
+}
+
+fn parse_unit_for(arch: Arch, input: &str) -> rg::Unit {
     let mut provider = TestSourceProvider::new();
     provider.files.insert("root.h".into(), input.into());
-    let v = parse_units(Box::new(provider));
+    let v = parse_units_with(Box::new(provider), Context::new(), Env::with_msvc(), arch);
     assert_eq!(v.len(), 1, "should generate single unit");
     v.into_iter().next().unwrap()
 }
 
-fn provider(name_source_pairs: &[(&str, &str)]) -> Box<dyn SourceProvider> {
+pub(crate) fn provider(name_source_pairs: &[(&str, &str)]) -> Box<TestSourceProvider> {
     let mut provider = TestSourceProvider::new();
     for (k, v) in name_source_pairs.iter().cloned() {
         provider.files.insert(k.into(), v.into());
@@ -577,7 +662,8 @@ fn typedef_floats() {
     ));
     unit.must_have_alias("FLOAT", &|d| d.typ.must_be("f32"));
     unit.must_have_alias("DOUBLE", &|d| d.typ.must_be("f64"));
-    unit.must_have_alias("LDOUBLE", &|d| d.typ.must_be("[u8; 12]"));
+    // MSVC's `long double` is a `double`
+    unit.must_have_alias("LDOUBLE", &|d| d.typ.must_be("f64"));
 }
 
 #[test]
@@ -591,8 +677,73 @@ fn multiple_typedefs() {
     unit.must_have_alias("LPUINT", &|d| {
         d.typ.must_be("uint".ctype().mut_pointer_name())
     });
+    // the pointer is const, not what it points to
     unit.must_have_alias("LCPUINT", &|d| {
-        d.typ.must_be("uint".ctype().const_pointer_name())
+        d.typ.must_be("uint".ctype().mut_pointer_name())
+    });
+}
+
+#[test]
+fn pointer_constness() {
+    let unit = parse_unit(indoc!(
+        "
+        typedef const char **PPCSTR;
+        typedef char *const *PCPSTR;
+        typedef const char *const *PCPCSTR;
+        typedef char **const CPPSTR;
+        typedef const char *(*GETNAME)(const int *const *ids);
+        struct S {
+            const char *Names[4];
+            char *const Ptrs[2];
+        };
+        void Print(const char *const argv[], char *const envp[2], const char values[4]);
+        "
+    ));
+    let c_char = "char".ctype();
+    let c_char = c_char.as_str();
+    unit.must_have_alias("PPCSTR", &|d| {
+        d.typ
+            .must_be(c_char.const_pointer_name().as_str().mut_pointer_name())
+    });
+    unit.must_have_alias("PCPSTR", &|d| {
+        d.typ
+            .must_be(c_char.mut_pointer_name().as_str().const_pointer_name())
+    });
+    unit.must_have_alias("PCPCSTR", &|d| {
+        d.typ
+            .must_be(c_char.const_pointer_name().as_str().const_pointer_name())
+    });
+    unit.must_have_alias("CPPSTR", &|d| {
+        d.typ
+            .must_be(c_char.mut_pointer_name().as_str().mut_pointer_name())
+    });
+    unit.must_have_alias("GETNAME", &|d| {
+        d.typ.must_be(format!(
+            r#"Option<unsafe extern "C" fn(*const *const {}) -> *const {}>"#,
+            "int".ctype(),
+            c_char
+        ))
+    });
+    unit.must_have_struct("S".struct_name(), &|s| {
+        s.must_have_field("Names", &|f| {
+            f.typ
+                .must_be(format!("[{}; 4]", c_char.const_pointer_name()))
+        });
+        s.must_have_field("Ptrs", &|f| {
+            f.typ.must_be(format!("[{}; 2]", c_char.mut_pointer_name()))
+        });
+    });
+    // arrays decay to pointers to their, maybe const, elements
+    unit.must_have_function("Print", &|d| {
+        d.must_have_param("argv", &|p| {
+            p.typ
+                .must_be(c_char.const_pointer_name().as_str().const_pointer_name())
+        });
+        d.must_have_param("envp", &|p| {
+            p.typ
+                .must_be(c_char.mut_pointer_name().as_str().const_pointer_name())
+        });
+        d.must_have_param("values", &|p| p.typ.must_be(c_char.const_pointer_name()));
     });
 }
 
@@ -690,6 +841,301 @@ fn nested_structs() {
     });
 }
 
+#[test]
+fn arrays() {
+    let unit = parse_unit(indoc!(
+        "
+        #define MAX_PATH 260
+        #define NAME_LEN (MAX_PATH + 1)
+        typedef unsigned short WCHAR;
+        typedef struct S {
+            WCHAR FileName[MAX_PATH];
+            WCHAR Name[NAME_LEN];
+            char Grid[2][3 * 4];
+            char *Names[4];
+            unsigned char Buffer[2 * sizeof(WCHAR)];
+            int Flexible[];
+        } S;
+        typedef int MATRIX[4][4];
+        typedef int (*PROW)[4];
+        void Fill(int values[16], const char names[2][8]);
+        "
+    ));
+    unit.must_have_struct("S".struct_name(), &|s| {
+        s.must_have_field("FileName", &|f| f.typ.must_be("[WCHAR; 260]"));
+        s.must_have_field("Name", &|f| f.typ.must_be("[WCHAR; 261]"));
+        s.must_have_field("Grid", &|f| {
+            f.typ.must_be(format!("[[{}; 12]; 2]", "char".ctype()))
+        });
+        s.must_have_field("Names", &|f| {
+            f.typ.must_be(format!(
+                "[{}; 4]",
+                "char".ctype().as_str().mut_pointer_name()
+            ))
+        });
+        s.must_have_field("Buffer", &|f| {
+            f.typ.must_be(format!(
+                "[{}; 2 * ::core::mem::size_of::<WCHAR>()]",
+                "uchar".ctype()
+            ))
+        });
+        s.must_have_field("Flexible", &|f| {
+            f.typ.must_be(format!("[{}; 0]", "int".ctype()))
+        });
+    });
+    unit.must_have_alias("MATRIX", &|d| {
+        d.typ.must_be(format!("[[{}; 4]; 4]", "int".ctype()))
+    });
+    unit.must_have_alias("PROW", &|d| {
+        d.typ.must_be(format!("*mut [{}; 4]", "int".ctype()))
+    });
+    unit.must_have_function("Fill", &|f| {
+        f.must_have_param("values", &|p| {
+            p.typ.must_be("int".ctype().as_str().mut_pointer_name())
+        });
+        f.must_have_param("names", &|p| {
+            p.typ.must_be(format!("*const [{}; 8]", "char".ctype()))
+        });
+    });
+}
+
+#[test]
+fn unary_operators() {
+    let unit = parse_unit(indoc!(
+        "
+        int Count(void);
+        typedef struct S {
+            char Negated[-(-4)];
+            char Mask[~0 & 0xF];
+            char Flag[!0 + +1];
+            char Called[Count()];
+        } S;
+        enum Flags { None = 0, All = ~None, Invalid = -1, Last = -All };
+        "
+    ));
+    unit.must_have_struct("S".struct_name(), &|s| {
+        s.must_have_field("Negated", &|f| {
+            f.typ.must_be(format!("[{}; 4]", "char".ctype()))
+        });
+        s.must_have_field("Mask", &|f| {
+            f.typ.must_be(format!("[{}; 15]", "char".ctype()))
+        });
+        s.must_have_field("Flag", &|f| {
+            f.typ.must_be(format!("[{}; 2]", "char".ctype()))
+        });
+        // translated anyway, but fails to compile
+        s.must_have_field("Called", &|f| {
+            f.typ.must_be(format!(
+                "[{}; compile_error!(\"unsupported C expression: function call\")]",
+                "char".ctype()
+            ))
+        });
+    });
+    unit.must_have_enum("Flags".enum_name(), &|d| {
+        let d = d.to_string();
+        assert!(d.contains("pub const All: Self = Self((!Self::None.0) as u32);"));
+        assert!(d.contains("pub const Invalid: Self = Self((-(1 as i64)) as u32);"));
+        assert!(d.contains("pub const Last: Self = Self((-(Self::All.0 as i64)) as u32);"));
+    });
+}
+
+#[test]
+fn unions() {
+    let unit = parse_unit(indoc!(
+        "
+        typedef unsigned long DWORD;
+        typedef long LONG;
+        typedef union _LARGE_INTEGER {
+            struct {
+                DWORD LowPart;
+                LONG HighPart;
+            } u;
+            long long QuadPart;
+        } LARGE_INTEGER, *PLARGE_INTEGER;
+        typedef struct _OVERLAPPED {
+            DWORD Internal;
+            union {
+                struct {
+                    DWORD Offset;
+                    DWORD OffsetHigh;
+                } s;
+                void *Pointer;
+            } u;
+        } OVERLAPPED;
+        union Forward;
+        typedef union Forward *PFORWARD;
+        "
+    ));
+    unit.must_have_struct_count(4);
+    unit.must_have_union("_LARGE_INTEGER".union_name(), &|d| {
+        d.must_have_field("QuadPart", &|f| f.typ.must_be("i64"));
+        d.must_have_field("u", &|f| {
+            let anon_name = f.typ.must_be_name();
+            unit.must_have_struct(&anon_name, &|s| {
+                s.must_have_field("LowPart", &|f| f.typ.must_be("DWORD"));
+                s.must_have_field("HighPart", &|f| f.typ.must_be("LONG"));
+            });
+        });
+    });
+    unit.must_have_alias("LARGE_INTEGER", &|d| {
+        d.typ.must_be("_LARGE_INTEGER".union_name())
+    });
+    unit.must_have_alias("PLARGE_INTEGER", &|d| {
+        d.typ
+            .must_be("_LARGE_INTEGER".union_name().mut_pointer_name())
+    });
+    unit.must_have_struct("_OVERLAPPED".struct_name(), &|s| {
+        s.must_have_field("Internal", &|f| f.typ.must_be("DWORD"));
+        s.must_have_field("u", &|f| {
+            let anon_name = f.typ.must_be_name();
+            assert!(anon_name.starts_with("union_"), "{:?}", anon_name);
+            unit.must_have_union(&anon_name, &|u| {
+                u.must_have_field("Pointer", &|f| f.typ.must_be("*mut ::core::ffi::c_void"));
+                u.must_have_field("s", &|f| {
+                    unit.must_have_struct(f.typ.must_be_name(), &|s| {
+                        s.must_have_field("Offset", &|f| f.typ.must_be("DWORD"));
+                    })
+                });
+            });
+        });
+    });
+    unit.must_have_struct("Forward".union_name(), &|s| s.must_be_opaque());
+    unit.must_have_alias("PFORWARD", &|d| {
+        d.typ.must_be("Forward".union_name().mut_pointer_name())
+    });
+}
+
+#[test]
+fn anonymous_members() {
+    let unit = parse_unit(indoc!(
+        "
+        typedef unsigned long DWORD;
+        typedef struct _OVERLAPPED {
+            DWORD Internal;
+            union {
+                struct {
+                    DWORD Offset;
+                    DWORD OffsetHigh;
+                };
+                void *Pointer;
+            };
+            union {
+                DWORD Flags;
+            };
+            DWORD s;
+        } OVERLAPPED;
+        "
+    ));
+    unit.must_have_struct("_OVERLAPPED".struct_name(), &|s| {
+        s.must_have_field("u", &|f| {
+            unit.must_have_union(f.typ.must_be_name(), &|u| {
+                u.must_have_field("Pointer", &|_| {});
+                // `s` is only taken in the outer struct
+                u.must_have_field("s", &|f| {
+                    unit.must_have_struct(f.typ.must_be_name(), &|s| {
+                        s.must_have_field("Offset", &|f| f.typ.must_be("DWORD"));
+                        s.must_have_field("OffsetHigh", &|f| f.typ.must_be("DWORD"));
+                    })
+                });
+            })
+        });
+        s.must_have_field("u2", &|f| {
+            unit.must_have_union(f.typ.must_be_name(), &|u| {
+                u.must_have_field("Flags", &|_| {})
+            })
+        });
+        s.must_have_field("s", &|f| f.typ.must_be("DWORD"));
+        assert_eq!(
+            s.fields
+                .iter()
+                .map(|f| f.name.value.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Internal", "u", "u2", "s"]
+        );
+    });
+}
+
+#[test]
+fn bitfields() {
+    let unit = parse_unit(indoc!(
+        "
+        typedef unsigned long DWORD;
+        #define KIND_BITS 3
+        struct S {
+            DWORD Valid : 1;
+            DWORD Kind : KIND_BITS;
+            int : 0;
+            DWORD Whole;
+            unsigned long long Large : sizeof(int) * 8 - 1;
+            unsigned long long Pointer : sizeof(void *) + sizeof(char[2]);
+            DWORD Unknown : sizeof(DWORD);
+        };
+        "
+    ));
+    unit.must_have_struct("S".struct_name(), &|s| {
+        s.must_have_field("Valid", &|f| assert_eq!(f.bits, Some(1)));
+        s.must_have_field("Kind", &|f| assert_eq!(f.bits, Some(3)));
+        s.must_have_field("", &|f| {
+            f.typ.must_be("int".ctype());
+            assert_eq!(f.bits, Some(0));
+        });
+        s.must_have_field("Whole", &|f| assert_eq!(f.bits, None));
+        s.must_have_field("Large", &|f| assert_eq!(f.bits, Some(31)));
+        s.must_have_field("Pointer", &|f| assert_eq!(f.bits, Some(10)));
+        // the size of typedefs isn't known to the translator
+        s.must_have_field("Unknown", &|f| assert_eq!(f.bits, None));
+    });
+}
+
+#[test]
+fn variadics() {
+    let unit = parse_unit(indoc!(
+        "
+        typedef char *LPSTR;
+        typedef const char *LPCSTR;
+        typedef char *va_list;
+        int wsprintfA(LPSTR buf, LPCSTR fmt, ...);
+        int wvsprintfA(LPSTR buf, LPCSTR fmt, va_list args);
+        typedef int (*PRINTF)(LPCSTR fmt, ...);
+        "
+    ));
+    unit.must_have_function("wsprintfA", &|d| {
+        assert_eq!(d.params.len(), 2);
+        assert!(d.variadic);
+        assert!(d
+            .to_string()
+            .contains("pub fn wsprintfA (buf: LPSTR, fmt: LPCSTR, ...) -> "));
+    });
+    // MSVC's <vadefs.h> declares `va_list` as a `char *` on x86 and x86-64,
+    // so functions taking one are regular functions
+    unit.must_have_function("wvsprintfA", &|d| assert!(!d.variadic));
+    unit.must_have_alias("va_list", &|d| {
+        d.typ.must_be("char".ctype().as_str().mut_pointer_name())
+    });
+    unit.must_have_alias("PRINTF", &|d| {
+        d.typ
+            .must_be(r#"Option<unsafe extern "C" fn(LPCSTR, ...) -> i32>"#)
+    });
+
+    // C23 allows variadics without named parameters, which Rust accepts
+    let ft = rg::FunctionType {
+        params: vec![],
+        variadic: true,
+        ret: None,
+        abi: rg::Abi::C,
+    };
+    assert_eq!(ft.to_string(), r#"Option<unsafe extern "C" fn(...)>"#);
+    let decl = rg::FunctionDeclaration {
+        name: rg::Identifier::name("AnyArgs"),
+        links: vec![],
+        params: vec![],
+        variadic: true,
+        ret: None,
+        abi: rg::Abi::C,
+    };
+    assert!(decl.to_string().contains("pub fn AnyArgs (...);"));
+}
+
 #[test]
 fn stddef_wchar_t() {
     let units = parse_units(provider(&[
@@ -730,7 +1176,8 @@ fn stddef_wchar_t() {
 
     let unit = units.iter().find(|&u| u.id == FileId(1)).unwrap();
     unit.must_have_function("foobar", &|f| {
-        f.must_have_param("c", &|p| p.typ.must_be("wchar_t"));
+        // the typedef is only there for C, wchar_t is a builtin type
+        f.must_have_param("c", &|p| p.typ.must_be("u16"));
         f.must_have_param("s", &|p| p.typ.must_be("size_t"));
     });
 }
@@ -895,3 +1342,236 @@ fn stdcall_in_function_pointer_typedef() {
         "
     ));
 }
+
+#[test]
+fn calling_conventions() {
+    let source = indoc!(
+        "
+        #define WINAPI __stdcall
+        typedef int BOOL;
+        BOOL WINAPI CloseHandle(void *h);
+        int __cdecl printf(const char *fmt, ...);
+        int __fastcall Fast(int a);
+        int Default(int a);
+        typedef BOOL (WINAPI *PCLOSE)(void *h);
+        typedef int (__fastcall *PFAST)(int a);
+        "
+    );
+
+    let unit = parse_unit_for(Arch::X86, source);
+    unit.must_have_function("CloseHandle", &|d| assert_eq!(d.abi, rg::Abi::System));
+    unit.must_have_function("printf", &|d| assert_eq!(d.abi, rg::Abi::C));
+    unit.must_have_function("Fast", &|d| {
+        assert!(d.to_string().starts_with("extern \"fastcall\" {"))
+    });
+    unit.must_have_function("Default", &|d| assert_eq!(d.abi, rg::Abi::C));
+    unit.must_have_alias("PCLOSE", &|d| {
+        d.typ
+            .must_be(r#"Option<unsafe extern "system" fn(*mut ::core::ffi::c_void) -> BOOL>"#)
+    });
+    unit.must_have_alias("PFAST", &|d| {
+        d.typ
+            .must_be(r#"Option<unsafe extern "fastcall" fn(i32) -> i32>"#)
+    });
+
+    // x86-64 has a single calling convention
+    let unit = parse_unit_for(Arch::X86_64, source);
+    unit.must_have_function("CloseHandle", &|d| assert_eq!(d.abi, rg::Abi::System));
+    unit.must_have_function("Fast", &|d| assert_eq!(d.abi, rg::Abi::C));
+    unit.must_have_alias("PFAST", &|d| {
+        d.typ.must_be(r#"Option<unsafe extern "C" fn(i32) -> i32>"#)
+    });
+}
+
+#[test]
+fn function_pointers() {
+    let unit = parse_unit(indoc!(
+        r#"
+        #define CALLBACK __stdcall
+        typedef long LRESULT;
+        typedef unsigned int UINT;
+        typedef void *HWND;
+        typedef LRESULT (CALLBACK *WNDPROC)(HWND, UINT, UINT, UINT);
+        typedef void (*PVOIDFN)(void);
+        typedef int EXCEPTION_ROUTINE(int code);
+        typedef EXCEPTION_ROUTINE *PEXCEPTION_ROUTINE;
+        typedef struct WNDCLASS {
+            WNDPROC lpfnWndProc;
+            int (*Compare)(const void *a, const void *b);
+            void (**Handlers)(int);
+        } WNDCLASS;
+        void EnumThings(int (CALLBACK *callback)(HWND hwnd, long param), long param);
+        "#
+    ));
+    let wndproc = r#"Option<unsafe extern "system" fn(HWND, UINT, UINT, UINT) -> LRESULT>"#;
+    unit.must_have_alias("WNDPROC", &|d| d.typ.must_be(wndproc));
+    unit.must_have_alias("PVOIDFN", &|d| {
+        d.typ.must_be(r#"Option<unsafe extern "C" fn()>"#)
+    });
+    unit.must_have_alias("EXCEPTION_ROUTINE", &|d| {
+        d.typ.must_be(format!(
+            r#"Option<unsafe extern "C" fn({int}) -> {int}>"#,
+            int = "int".ctype()
+        ))
+    });
+    // function typedefs are function pointers already
+    unit.must_have_alias("PEXCEPTION_ROUTINE", &|d| {
+        d.typ.must_be("EXCEPTION_ROUTINE")
+    });
+    unit.must_have_struct("WNDCLASS".struct_name(), &|s| {
+        s.must_have_field("lpfnWndProc", &|f| f.typ.must_be("WNDPROC"));
+        s.must_have_field("Compare", &|f| {
+            f.typ.must_be(format!(
+                r#"Option<unsafe extern "C" fn(*const ::core::ffi::c_void, *const ::core::ffi::c_void) -> {}>"#,
+                "int".ctype()
+            ))
+        });
+        s.must_have_field("Handlers", &|f| {
+            f.typ.must_be(format!(
+                r#"*mut Option<unsafe extern "C" fn({})>"#,
+                "int".ctype()
+            ))
+        });
+    });
+    unit.must_have_function("EnumThings", &|d| {
+        assert!(d.ret.is_none());
+        d.must_have_param("callback", &|p| {
+            p.typ.must_be(format!(
+                r#"Option<unsafe extern "system" fn(HWND, {long}) -> {int}>"#,
+                long = "long".ctype(),
+                int = "int".ctype()
+            ))
+        });
+        d.must_have_param("param", &|p| p.typ.must_be("long".ctype()));
+    });
+}
+
+#[test]
+fn has_include() {
+    let units = parse_units(provider(&[
+        ("present.h", "typedef int PRESENT;"),
+        (
+            "root.h",
+            indoc!(
+                "
+                #if __has_include(<present.h>)
+                #include <present.h>
+                #endif
+                #if __has_include(\"missing.h\")
+                #include \"missing.h\"
+                #endif
+                #if defined(__has_include) && !__has_attribute(packed)
+                typedef int NO_PACKED;
+                #endif
+                "
+            ),
+        ),
+    ]));
+    assert_eq!(units.len(), 2, "should only include present.h");
+    units[0].must_have_alias("NO_PACKED", &|_| {});
+    units[1].must_have_alias("PRESENT", &|_| {});
+}
+
+#[test]
+fn pragma_operators() {
+    let unit = parse_unit(indoc!(
+        r#"
+        #define PACKED_BEGIN _Pragma("pack(push, 1)")
+        #define WARNING_PUSH __pragma(warning(push)) __pragma(warning(disable: 4201))
+        PACKED_BEGIN
+        typedef struct S {
+            WARNING_PUSH
+            int a;
+        } S;
+        __pragma(warning(pop))
+        __pragma(comment(lib,
+            "wintrust"))
+        long Verify(void);
+        "#
+    ));
+    unit.must_have_struct("S".struct_name(), &|s| s.must_have_field("a", &|_| {}));
+    // arguments continuing on the next line are joined
+    unit.must_have_function("Verify", &|f| {
+        assert_eq!(f.links, vec!["wintrust".to_string()])
+    });
+}
+
+#[test]
+fn pragma_comment_lib() {
+    let unit = parse_unit(indoc!(
+        r#"
+        #pragma comment(lib, "wintrust.lib")
+        __pragma(comment(lib, "crypt32"))
+        long WinVerifyTrust(void *hwnd);
+        "#
+    ));
+    unit.must_have_function("WinVerifyTrust", &|f| {
+        assert_eq!(f.links, vec!["wintrust".to_string(), "crypt32".to_string()]);
+        assert!(f.to_string().starts_with(
+            "#[link(name = \"wintrust\")]\n#[link(name = \"crypt32\")]\nextern \"C\" {"
+        ));
+    });
+}
+
+#[test]
+fn extern_statics() {
+    let unit = parse_unit(indoc!(
+        r#"
+        #pragma comment(lib, "ole32.lib")
+        typedef struct _GUID { unsigned long Data1; } GUID;
+        typedef GUID IID;
+        extern const IID IID_IUnknown;
+        __declspec(dllimport) extern int errno_value;
+        __declspec(dllimport) const char *const names[4];
+        extern const char *last_name;
+        extern void (*on_exit)(int code);
+        static int private_counter;
+        int tentative;
+        "#
+    ));
+    unit.must_have_static("IID_IUnknown", &|d| {
+        d.typ.must_be("IID");
+        assert!(!d.mutable);
+        assert!(!d.dllimport);
+        // only dllimport data goes through the DLL's import library
+        assert_eq!(
+            d.to_string(),
+            "extern \"C\" {\n    pub static IID_IUnknown: IID;\n}\n"
+        );
+    });
+    unit.must_have_static("errno_value", &|d| {
+        assert!(d.mutable);
+        assert!(d.dllimport);
+        assert_eq!(
+            d.to_string(),
+            indoc!(
+                r#"
+                #[link(name = "ole32")]
+                extern "C" {
+                    pub static mut errno_value: i32;
+                }
+                "#
+            )
+        );
+    });
+    unit.must_have_static("names", &|d| {
+        assert!(!d.mutable);
+        assert!(d.dllimport);
+    });
+    unit.must_have_static("last_name", &|d| {
+        d.typ.must_be("char".ctype().as_str().const_pointer_name());
+        assert!(d.mutable);
+    });
+    unit.must_have_static("on_exit", &|d| {
+        d.typ.must_be(r#"Option<unsafe extern "C" fn(i32)>"#);
+    });
+    let statics = unit
+        .toplevels
+        .iter()
+        .filter(|tl| matches!(tl, rg::TopLevel::StaticDeclaration(_)))
+        .count();
+    assert_eq!(
+        statics, 5,
+        "static and tentative definitions aren't imported"
+    );
+}
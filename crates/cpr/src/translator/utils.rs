@@ -62,6 +62,8 @@ impl SpecifierQualifierExt for ast::SpecifierQualifier {
 
 pub(crate) trait DeclarationExt {
     fn get_storage_class(&self) -> Option<&ast::StorageClassSpecifier>;
+    /// Has `__declspec(dllimport)` among its specifiers
+    fn is_dllimport(&self) -> bool;
 }
 
 impl DeclarationExt for ast::Declaration {
@@ -73,6 +75,18 @@ impl DeclarationExt for ast::Declaration {
         }
         None
     }
+
+    fn is_dllimport(&self) -> bool {
+        nodes(&self.specifiers[..])
+            .flat_map(|dspec| match dspec {
+                ast::DeclarationSpecifier::Extension(exts) => &exts[..],
+                _ => &[],
+            })
+            .map(borrow_node)
+            .any(|ext| {
+                matches!(ext, ast::Extension::Attribute(attr) if attr.name.node == "dllimport")
+            })
+    }
 }
 
 pub(crate) trait DeclaratorExt {
@@ -83,6 +97,10 @@ pub(crate) trait DeclaratorExt {
 
     fn get_function(&self) -> Option<&ast::FunctionDeclarator>;
     fn get_identifier(&self) -> Option<&ast::Identifier>;
+    fn get_nested(&self) -> Option<&ast::Declarator>;
+    /// Identifier of the declarator, or of the innermost nested declarator,
+    /// ie. `f` in `int (*f)(int)`
+    fn find_identifier(&self) -> Option<&ast::Identifier>;
 }
 
 impl DeclaratorExt for ast::Declarator {
@@ -109,11 +127,27 @@ impl DeclaratorExt for ast::Declarator {
             None
         }
     }
+
+    fn get_nested(&self) -> Option<&ast::Declarator> {
+        if let ast::DeclaratorKind::Declarator(nested) = &self.kind.node {
+            Some(&nested.node)
+        } else {
+            None
+        }
+    }
+
+    fn find_identifier(&self) -> Option<&ast::Identifier> {
+        match self.get_nested() {
+            Some(nested) => nested.find_identifier(),
+            None => self.get_identifier(),
+        }
+    }
 }
 
 pub(crate) trait VoidExt {
     fn takes_nothing(&self) -> bool;
     fn returns_nothing(&self) -> bool;
+    fn is_variadic(&self) -> bool;
 }
 
 impl VoidExt for ast::FunctionDeclarator {
@@ -129,6 +163,10 @@ impl VoidExt for ast::FunctionDeclarator {
     fn returns_nothing(&self) -> bool {
         unimplemented!()
     }
+
+    fn is_variadic(&self) -> bool {
+        matches!(self.ellipsis, ast::Ellipsis::Some)
+    }
 }
 
 pub(crate) trait Typed: std::fmt::Debug {
@@ -161,6 +199,33 @@ pub(crate) trait Typed: std::fmt::Debug {
         self.specquals().any(|s| s.is_const())
     }
 
+    /// The `__stdcall`, `__fastcall`... keyword of a function or function
+    /// pointer, which may be a qualifier, ie. `BOOL __stdcall f()`, or come
+    /// with the declarator, ie. `BOOL (__stdcall *f)()`
+    fn calling_convention(&self) -> Option<ast::CallingConvention> {
+        let qualifier = self.specquals().find_map(|s| match s {
+            ast::SpecifierQualifier::TypeQualifier(Node {
+                node: ast::TypeQualifier::CallingConvention(cc),
+                ..
+            }) => Some(cc),
+            _ => None,
+        });
+        if qualifier.is_some() {
+            return qualifier;
+        }
+
+        let mut dtor = self.declarator();
+        while let Some(d) = dtor {
+            for ext in nodes(&d.extensions[..]) {
+                if let ast::Extension::CallingConvention(cc) = ext {
+                    return Some(cc.clone());
+                }
+            }
+            dtor = d.get_nested();
+        }
+        None
+    }
+
     fn pointer_depth(&self) -> usize {
         self.declarator().map(|d| d.pointer_depth()).unwrap_or(0)
     }
@@ -207,33 +272,20 @@ impl<'a> Typed for DeclTuple<'a> {
     }
 
     fn specifiers(&self) -> Box<dyn Iterator<Item = &dyn AsSpecifierQualifier> + '_> {
-        let dtion_specs = nodes(&self.dtion.specifiers[..]).map(|x| x as &dyn AsSpecifierQualifier);
-        let dtor_specs = nodes(&self.dtor.derived[..])
-            .map(
-                |dd| -> Box<dyn Iterator<Item = &dyn AsSpecifierQualifier>> {
-                    match dd {
-                        ast::DerivedDeclarator::Pointer(pquals) => {
-                            Box::new(nodes(&pquals[..]).map(|x| x as &dyn AsSpecifierQualifier))
-                        }
-                        _ => Box::new(std::iter::empty()),
-                    }
-                },
-            )
-            .flatten()
-            .map(|x| x as &dyn AsSpecifierQualifier);
-        Box::new(dtion_specs.chain(dtor_specs))
+        Box::new(nodes(&self.dtion.specifiers[..]).map(|x| x as &dyn AsSpecifierQualifier))
     }
 }
 
 #[derive(Debug)]
 pub(crate) struct StructFieldTuple<'a> {
     pub(crate) field: &'a ast::StructField,
-    pub(crate) dtor: &'a ast::Declarator,
+    /// `None` for unnamed bitfields
+    pub(crate) dtor: Option<&'a ast::Declarator>,
 }
 
 impl<'a> Typed for StructFieldTuple<'a> {
     fn declarator(&self) -> Option<&ast::Declarator> {
-        Some(&self.dtor)
+        self.dtor
     }
     fn specifiers(&self) -> Box<dyn Iterator<Item = &dyn AsSpecifierQualifier> + '_> {
         Box::new(nodes(&self.field.specifiers[..]).map(|x| x as &dyn AsSpecifierQualifier))
@@ -1596,7 +1596,11 @@ rule sal_struct_annotation() -> Node<Extension> = node(<sal_struct_annotation0()
 
 rule calling_convention() -> CallingConvention =
     K(<"__cdecl">) { CallingConvention::Cdecl } /
-    K(<"__stdcall">) { CallingConvention::Stdcall }
+    K(<"__stdcall">) { CallingConvention::Stdcall } /
+    K(<"__fastcall">) { CallingConvention::Fastcall } /
+    K(<"__vectorcall">) { CallingConvention::Vectorcall } /
+    K(<"__thiscall">) { CallingConvention::Thiscall } /
+    K(<"__clrcall">) { CallingConvention::Clrcall }
 
 rule calling_convention_extension_list() -> Vec<Node<Extension>> =
     l:list0(<node(<(cc:calling_convention() { Extension::CallingConvention(cc) })>)>) _ { l }
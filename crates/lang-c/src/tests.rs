@@ -2222,6 +2222,8 @@ fn test_msvc_calling_convention() {
     let mut env = Env::with_msvc();
     let env = &env.for_parser();
     translation_unit("extern int __stdcall test();", env).unwrap();
+    translation_unit("extern int __fastcall test2(int a);", env).unwrap();
+    translation_unit("typedef int (__vectorcall *test3)(int a);", env).unwrap();
 }
 
 #[test]